@@ -2,5 +2,10 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::compile_protos("../../proto/trading.proto")?;
+    // Rich error model: RejectReason dikirim di google.rpc.ErrorInfo pada detail gRPC Status
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .compile(&["../../proto/google/rpc/status.proto", "../../proto/google/rpc/error_details.proto"], &["../../proto"])?;
     Ok(())
 }
\ No newline at end of file
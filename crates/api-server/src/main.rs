@@ -1,31 +1,66 @@
 // crates/api-server/src/main.rs
 
 use std::net::SocketAddr;
-use tonic::{transport::Server, Request, Response, Status};
+use prost::Message as _;
+use tonic::{transport::Server, Code, Request, Response, Status};
 use tokio::sync::{mpsc, oneshot, broadcast};
-use engine_core::processor::{MarketProcessor, Command};
-use engine_core::{Side as EngineSide, EngineEvent};
+use serde::Deserialize;
+use engine_core::error::EngineError;
+use engine_core::processor::{Command, MarketConfig, MarketEvent};
+use engine_core::orders::{OrderState, OrderStatus};
+use engine_core::registry::{self, MarketRegistry};
+use engine_core::wal::Durability;
+use engine_core::fees::FeeSchedule;
+use engine_core::instrument::InstrumentSpec;
+use engine_core::ledger::Asset as EngineAsset;
+use engine_core::{
+    Side as EngineSide, EngineEvent, TimeInForce as EngineTimeInForce, PostOnly,
+    SelfTradePrevention as EngineStp, StpPolicy, RejectReason as EngineRejectReason, CancelReason, CancelFilter
+};
 use trading::trading_engine_server::{TradingEngine, TradingEngineServer};
 use trading:: {
-    PlaceOrderRequest, PlaceOrderResponse, CancelOrderRequest, CancelOrderResponse, 
-    DepthRequest, DepthResponse, OrderLevel as ProtoOrderLevel, TradeExecution, Side as ProtoSide
+    PlaceOrderRequest, PlaceOrderResponse, MarketOrderRequest, StopOrderRequest, CancelOrderRequest, CancelOrderResponse,
+    MassCancelRequest, MassCancelResponse, GetOrderRequest, OrderStatus as ProtoOrderStatus, OrderState as ProtoOrderState,
+    ListOpenOrdersRequest, ListOpenOrdersResponse, SnapshotRequest, SnapshotResponse, ResumeRequest, ResumeResponse,
+    AmendOrderRequest, AmendOrderResponse, FundsRequest, FundsResponse, Asset as ProtoAsset,
+    DepthRequest, DepthResponse, InstrumentRequest, InstrumentResponse, OrderLevel as ProtoOrderLevel, TradeExecution, Side as ProtoSide,
+    TimeInForce as ProtoTimeInForce, PostOnlyMode, StpMode as ProtoStp, RejectReason as ProtoRejectReason
 };
 use axum:: {
     extract::ws::{Message, WebSocket, WebSocketUpgrade},
-    extract::State,
+    extract::{Query, State},
     response::IntoResponse,
     routing::get,
     Router,
 };
 
+// Kode hasil generate dari proto, komentar proto ikut menjadi doc comment
+#[allow(clippy::doc_lazy_continuation)]
 pub mod trading {
     tonic::include_proto!("trading");
 }
 
+pub mod rpc {
+    tonic::include_proto!("google.rpc");
+}
+
+// Market yang dijalankan jika VELOCITY_MARKETS tidak di-set
+const DEFAULT_MARKETS: &str = "SOL_USDC";
+
+// Domain google.rpc.ErrorInfo untuk request yang ditolak engine
+const ERROR_DOMAIN: &str = "velocity-dex";
+
 // Struct Service gRPC
 pub struct TradingService {
-    // Channel untuk mengirim command ke MarketProcessor (Actor)
-    processor_sender: mpsc::Sender<Command>,
+    // Channel command ke MarketProcessor (Actor) per symbol
+    markets: MarketRegistry,
+}
+
+impl TradingService {
+    // Routing request ke actor market yang sesuai dengan symbol
+    fn market(&self, symbol: &str) -> Option<&mpsc::Sender<Command>> {
+        self.markets.sender(symbol)
+    }
 }
 
 #[tonic::async_trait]
@@ -37,11 +72,13 @@ impl TradingEngine for TradingService {
         let req = request.into_inner();
 
         // 1. Validasi & Konversi Input (Proto -> Internal)
-        let side = match ProtoSide::try_from(req.side).unwrap_or(ProtoSide::Unspecified) {
-            ProtoSide::Bid => EngineSide::Bid,
-            ProtoSide::Ask => EngineSide::Ask,
-            ProtoSide::Unspecified => return Err(Status::invalid_argument("Side is required")),
-        };
+        let side = parse_side(req.side).ok_or_else(|| Status::invalid_argument("Side is required"))?;
+        let time_in_force = parse_time_in_force(req.time_in_force, req.expire_at)
+            .ok_or_else(|| Status::invalid_argument("Unknown time in force (GTD requires expire_at)"))?;
+        let post_only = parse_post_only(req.post_only)
+            .ok_or_else(|| Status::invalid_argument("Unknown post-only mode"))?;
+        let stp = parse_stp(req.stp_mode, req.stp_group)
+            .ok_or_else(|| Status::invalid_argument("Unknown self-trade prevention mode"))?;
 
         // 2. Siapkan Response Channel (One-Shot)
         let (resp_tx, resp_rx) = oneshot::channel();
@@ -53,80 +90,376 @@ impl TradingEngine for TradingService {
             side,
             price: req.price,
             quantity: req.quantity,
+            time_in_force,
+            post_only,
+            stp,
+            display_quantity: (req.display_quantity != 0).then_some(req.display_quantity),
+            client_order_id: parse_client_order_id(req.client_order_id),
             responder: resp_tx,
         };
 
-        // Kirim ke actor (jika channel penuh/tutup, berarti engine mati)
-        self.processor_sender
+        // Kirim ke actor market (jika channel penuh/tutup, berarti engine mati)
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
             .send(command)
             .await
             .map_err(|_| Status::internal("Engine is down"))?;
 
         // 4. Tunggu Hasil dari Engine
-        let events = resp_rx.await.map_err(|_| Status::internal("Engine failed to respond"))?;
+        let events = resp_rx.await
+            .map_err(|_| Status::internal("Engine failed to respond"))?
+            .map_err(engine_error_to_status)?;
 
         // 5. Konversi Event Engine ke Response Proto
-        let mut fills = Vec::new();
-        let mut success = false;
+        Ok(Response::new(build_place_response(req.order_id, events)))
+    }
+
+    async fn place_market_order(
+        &self,
+        request: Request<MarketOrderRequest>,
+    ) -> Result<Response<PlaceOrderResponse>, Status> {
+        let req = request.into_inner();
+        let side = parse_side(req.side).ok_or_else(|| Status::invalid_argument("Side is required"))?;
+        let stp = parse_stp(req.stp_mode, req.stp_group)
+            .ok_or_else(|| Status::invalid_argument("Unknown self-trade prevention mode"))?;
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::PlaceMarketOrder {
+                user_id: req.user_id,
+                order_id: req.order_id,
+                side,
+                quantity: req.quantity,
+                stp,
+                client_order_id: parse_client_order_id(req.client_order_id),
+                responder: resp_tx,
+            })
+            .await
+            .map_err(|_| Status::internal("Engine is down"))?;
+
+        let events = resp_rx.await
+            .map_err(|_| Status::internal("Engine failed to respond"))?
+            .map_err(engine_error_to_status)?;
+
+        Ok(Response::new(build_place_response(req.order_id, events)))
+    }
+
+    async fn place_stop_order(
+        &self,
+        request: Request<StopOrderRequest>,
+    ) -> Result<Response<PlaceOrderResponse>, Status> {
+        let req = request.into_inner();
+        let side = parse_side(req.side).ok_or_else(|| Status::invalid_argument("Side is required"))?;
+        let stp = parse_stp(req.stp_mode, req.stp_group)
+            .ok_or_else(|| Status::invalid_argument("Unknown self-trade prevention mode"))?;
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::PlaceStopOrder {
+                user_id: req.user_id,
+                order_id: req.order_id,
+                side,
+                trigger_price: req.trigger_price,
+                limit_price: (req.limit_price != 0).then_some(req.limit_price),
+                quantity: req.quantity,
+                stp,
+                client_order_id: parse_client_order_id(req.client_order_id),
+                responder: resp_tx,
+            })
+            .await
+            .map_err(|_| Status::internal("Engine is down"))?;
+
+        let events = resp_rx.await
+            .map_err(|_| Status::internal("Engine failed to respond"))?
+            .map_err(engine_error_to_status)?;
+
+        Ok(Response::new(build_place_response(req.order_id, events)))
+    }
+
+    async fn cancel_order(
+        &self,
+        request: Request<CancelOrderRequest>,
+    ) -> Result<Response<CancelOrderResponse>, Status> {
+        let req = request.into_inner();
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        // 1. Kirim Command ke Actor
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::CancelOrder {
+                user_id: req.user_id,
+                order_id: req.order_id,
+                client_order_id: parse_client_order_id(req.client_order_id),
+                responder: resp_tx,
+            })
+            .await
+            .map_err(|_| Status::internal("Engine down"))?;
+
+        // 2. Tunggu hasil
+        let events = resp_rx.await
+            .map_err(|_| Status::internal("No response"))?
+            .map_err(engine_error_to_status)?;
+
+        // 3. Cek apakah ada event OrderCancelled (atau StopCancelled untuk stop order)
+        let remaining_qty = events.iter().find_map(|e| match *e {
+            EngineEvent::OrderCancelled { quantity, .. } | EngineEvent::StopCancelled { quantity, .. } => Some(quantity),
+            _ => None,
+        });
+
+        Ok(Response::new(CancelOrderResponse {
+            success: remaining_qty.is_some(),
+            remaining_qty: remaining_qty.unwrap_or(0),
+        }))
+    }
+
+    async fn mass_cancel(
+        &self,
+        request: Request<MassCancelRequest>,
+    ) -> Result<Response<MassCancelResponse>, Status> {
+        let req = request.into_inner();
+        let filter = CancelFilter {
+            side: parse_side(req.side),
+            min_price: (req.min_price != 0).then_some(req.min_price),
+            max_price: (req.max_price != 0).then_some(req.max_price),
+        };
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::MassCancel {
+                user_id: req.user_id,
+                filter,
+                responder: resp_tx,
+            })
+            .await
+            .map_err(|_| Status::internal("Engine down"))?;
+
+        let events = resp_rx.await
+            .map_err(|_| Status::internal("No response"))?
+            .map_err(engine_error_to_status)?;
 
+        let mut response = MassCancelResponse::default();
         for event in events {
             match event {
-                EngineEvent::OrderPlaced { id, .. } if id == req.order_id => {
-                    success = true; // Order masuk book (Maker)
+                EngineEvent::OrderCancelled { id, .. } | EngineEvent::StopCancelled { id, .. } => {
+                    response.order_ids.push(id);
                 }
-                EngineEvent::TradeExecuted { maker_id, taker_id, price, quantity } => {
-                    // Jika kita adalah taker, catat eksekusi ini
-                    if taker_id == req.order_id {
-                        fills.push(TradeExecution {
-                            maker_order_id: maker_id,
-                            price,
-                            quantity,
-                        });
-                        success = true; // Terjadi trade (Taker)
-                    }
+                EngineEvent::MassCancelled { count, .. } => response.cancelled_count = count,
+                _ => {}
+            }
+        }
+
+        Ok(Response::new(response))
+    }
+
+    async fn amend_order(
+        &self,
+        request: Request<AmendOrderRequest>,
+    ) -> Result<Response<AmendOrderResponse>, Status> {
+        let req = request.into_inner();
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::AmendOrder {
+                user_id: req.user_id,
+                order_id: req.order_id,
+                price: req.price,
+                quantity: req.quantity,
+                responder: resp_tx,
+            })
+            .await
+            .map_err(|_| Status::internal("Engine down"))?;
+
+        let events = resp_rx.await
+            .map_err(|_| Status::internal("No response"))?
+            .map_err(engine_error_to_status)?;
+
+        let mut response = AmendOrderResponse::default();
+
+        for event in events {
+            match event {
+                EngineEvent::OrderAmended { id, quantity, priority_kept, .. } if id == req.order_id => {
+                    response.success = true;
+                    response.priority_kept = priority_kept;
+                    response.resting_quantity = quantity;
                 }
-                EngineEvent::OrderCancelled { .. } => {
+                EngineEvent::TradeExecuted { maker_id, taker_id, price, quantity, maker_fee, taker_fee, .. } if taker_id == req.order_id => {
+                    response.fills.push(TradeExecution {
+                        maker_order_id: maker_id,
+                        price,
+                        quantity,
+                        maker_fee,
+                        taker_fee,
+                    });
                 }
                 _ => {}
             }
         }
 
-        Ok(Response::new(PlaceOrderResponse {
-            success,
-            message: if success { "Order Processed".to_string() } else { "Order Rejected".to_string() },
-            fills,
-        }))
+        Ok(Response::new(response))
     }
 
-    async fn cancel_order(
+    async fn deposit(
         &self,
-        request: Request<CancelOrderRequest>,
-    ) -> Result<Response<CancelOrderResponse>, Status> {
+        request: Request<FundsRequest>,
+    ) -> Result<Response<FundsResponse>, Status> {
         let req = request.into_inner();
+        let asset = parse_asset(req.asset).ok_or_else(|| Status::invalid_argument("Asset is required"))?;
+        if req.amount == 0 {
+            return Err(Status::invalid_argument("Amount must be positive"));
+        }
+        if req.journal_id == 0 {
+            return Err(Status::invalid_argument("Journal id is required"));
+        }
+
         let (resp_tx, resp_rx) = oneshot::channel();
 
-        // 1. Kirim Command ke Actor
-        self.processor_sender
-            .send(Command::CancelOrder {
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::Deposit {
+                journal_id: req.journal_id,
+                user_id: req.user_id,
+                asset,
+                amount: req.amount,
+                responder: resp_tx,
+            })
+            .await
+            .map_err(|_| Status::internal("Engine down"))?;
+
+        let events = resp_rx.await
+            .map_err(|_| Status::internal("No response"))?
+            .map_err(engine_error_to_status)?;
+
+        Ok(Response::new(build_funds_response(req.journal_id, events)))
+    }
+
+    async fn withdraw(
+        &self,
+        request: Request<FundsRequest>,
+    ) -> Result<Response<FundsResponse>, Status> {
+        let req = request.into_inner();
+        let asset = parse_asset(req.asset).ok_or_else(|| Status::invalid_argument("Asset is required"))?;
+        if req.amount == 0 {
+            return Err(Status::invalid_argument("Amount must be positive"));
+        }
+        if req.journal_id == 0 {
+            return Err(Status::invalid_argument("Journal id is required"));
+        }
+
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::Withdraw {
+                journal_id: req.journal_id,
+                user_id: req.user_id,
+                asset,
+                amount: req.amount,
+                responder: resp_tx,
+            })
+            .await
+            .map_err(|_| Status::internal("Engine down"))?;
+
+        let events = resp_rx.await
+            .map_err(|_| Status::internal("No response"))?
+            .map_err(engine_error_to_status)?;
+
+        Ok(Response::new(build_funds_response(req.journal_id, events)))
+    }
+
+    async fn get_order(
+        &self,
+        request: Request<GetOrderRequest>,
+    ) -> Result<Response<ProtoOrderStatus>, Status> {
+        let req = request.into_inner();
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::GetOrder {
                 user_id: req.user_id,
                 order_id: req.order_id,
+                client_order_id: parse_client_order_id(req.client_order_id),
                 responder: resp_tx,
             })
             .await
             .map_err(|_| Status::internal("Engine down"))?;
 
-        // 2. Tunggu hasil
-        let events = resp_rx.await.map_err(|_| Status::internal("No response"))?;
+        let status = resp_rx.await
+            .map_err(|_| Status::internal("No response"))?
+            .map_err(engine_error_to_status)?;
 
-        // 3. Cek apakah ada event OrderCancelled
-        let success = events.iter().any(|e| matches!(e, EngineEvent::OrderCancelled { .. }));
+        Ok(Response::new(order_status_to_proto(status)))
+    }
 
-        Ok(Response::new(CancelOrderResponse {
-            success,
-            remaining_qty: 0,
+    async fn list_open_orders(
+        &self,
+        request: Request<ListOpenOrdersRequest>,
+    ) -> Result<Response<ListOpenOrdersResponse>, Status> {
+        let req = request.into_inner();
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::ListOpenOrders {
+                user_id: req.user_id,
+                responder: resp_tx,
+            })
+            .await
+            .map_err(|_| Status::internal("Engine down"))?;
+
+        let orders = resp_rx.await.map_err(|_| Status::internal("No response"))?;
+
+        Ok(Response::new(ListOpenOrdersResponse {
+            orders: orders.into_iter().map(order_status_to_proto).collect(),
         }))
     }
 
+    async fn take_snapshot(
+        &self,
+        request: Request<SnapshotRequest>,
+    ) -> Result<Response<SnapshotResponse>, Status> {
+        let req = request.into_inner();
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::Snapshot { responder: resp_tx })
+            .await
+            .map_err(|_| Status::internal("Engine down"))?;
+
+        let seq = resp_rx.await
+            .map_err(|_| Status::internal("No response"))?
+            .map_err(|e| Status::internal(format!("Snapshot failed: {}", e)))?;
+
+        Ok(Response::new(SnapshotResponse { seq }))
+    }
+
+    async fn resume_market(
+        &self,
+        request: Request<ResumeRequest>,
+    ) -> Result<Response<ResumeResponse>, Status> {
+        let req = request.into_inner();
+        let (resp_tx, resp_rx) = oneshot::channel();
+
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
+            .send(Command::Resume { responder: resp_tx })
+            .await
+            .map_err(|_| Status::internal("Engine down"))?;
+
+        resp_rx.await
+            .map_err(|_| Status::internal("No response"))?
+            .map_err(|e| Status::unavailable(format!("WAL still unavailable: {}", e)))?;
+
+        Ok(Response::new(ResumeResponse {}))
+    }
+
     async fn get_order_book_depth(
         &self,
         request: Request<DepthRequest>,
@@ -137,7 +470,8 @@ impl TradingEngine for TradingService {
         let (resp_tx, resp_rx) = oneshot::channel();
 
         // Kirim command ke Engine Actor
-        self.processor_sender
+        self.market(&req.symbol)
+            .ok_or_else(|| unknown_market(&req.symbol))?
             .send(Command::GetDepth {
                 limit,
                 responder: resp_tx,
@@ -165,29 +499,318 @@ impl TradingEngine for TradingService {
             sequence_id: 0, 
         }))
     }
+
+    async fn get_instrument(
+        &self,
+        request: Request<InstrumentRequest>,
+    ) -> Result<Response<InstrumentResponse>, Status> {
+        let req = request.into_inner();
+        let spec = self.markets.spec(&req.symbol).ok_or_else(|| unknown_market(&req.symbol))?;
+
+        Ok(Response::new(InstrumentResponse {
+            symbol: req.symbol,
+            tick_size: spec.tick_size,
+            lot_size: spec.lot_size,
+            min_quantity: spec.min_quantity,
+            max_quantity: spec.max_quantity,
+            min_notional: u64::try_from(spec.min_notional).unwrap_or(u64::MAX),
+            base_decimals: spec.base_decimals.into(),
+            quote_decimals: spec.quote_decimals.into(),
+        }))
+    }
+}
+
+// Symbol yang tidak terdaftar di registry
+fn unknown_market(symbol: &str) -> Status {
+    Status::not_found(format!("Unknown market: {:?}", symbol))
+}
+
+// Konversi Side dari Proto ke Engine
+fn parse_side(side: i32) -> Option<EngineSide> {
+    match ProtoSide::try_from(side).unwrap_or(ProtoSide::Unspecified) {
+        ProtoSide::Bid => Some(EngineSide::Bid),
+        ProtoSide::Ask => Some(EngineSide::Ask),
+        ProtoSide::Unspecified => None,
+    }
+}
+
+// Konversi Asset dari Proto ke Engine
+fn parse_asset(asset: i32) -> Option<EngineAsset> {
+    match ProtoAsset::try_from(asset).ok()? {
+        ProtoAsset::Base => Some(EngineAsset::Base),
+        ProtoAsset::Quote => Some(EngineAsset::Quote),
+        ProtoAsset::Unspecified => None,
+    }
+}
+
+// Konversi Time-In-Force dari Proto ke Engine (Unspecified = GTC)
+fn parse_time_in_force(tif: i32, expire_at: u64) -> Option<EngineTimeInForce> {
+    match ProtoTimeInForce::try_from(tif).ok()? {
+        ProtoTimeInForce::Unspecified | ProtoTimeInForce::Gtc => Some(EngineTimeInForce::Gtc),
+        ProtoTimeInForce::Ioc => Some(EngineTimeInForce::Ioc),
+        ProtoTimeInForce::Fok => Some(EngineTimeInForce::Fok),
+        ProtoTimeInForce::Gtd if expire_at > 0 => Some(EngineTimeInForce::Gtd { expires_at: expire_at }),
+        ProtoTimeInForce::Gtd => None,
+    }
+}
+
+// Konversi Post-Only mode dari Proto ke Engine
+fn parse_post_only(mode: i32) -> Option<PostOnly> {
+    match PostOnlyMode::try_from(mode).ok()? {
+        PostOnlyMode::PostOnlyDisabled => Some(PostOnly::Disabled),
+        PostOnlyMode::PostOnlyReject => Some(PostOnly::Reject),
+        PostOnlyMode::PostOnlySlide => Some(PostOnly::Slide),
+    }
+}
+
+// Konversi STP mode + group dari Proto ke Engine (Unspecified = Cancel Maker, group 0 = tanpa group)
+fn parse_stp(mode: i32, group: u64) -> Option<StpPolicy> {
+    let mode = match ProtoStp::try_from(mode).ok()? {
+        ProtoStp::Unspecified | ProtoStp::CancelMaker => EngineStp::CancelMaker,
+        ProtoStp::CancelTaker => EngineStp::CancelTaker,
+        ProtoStp::CancelBoth => EngineStp::CancelBoth,
+        ProtoStp::DecrementAndCancel => EngineStp::DecrementAndCancel,
+    };
+
+    Some(StpPolicy {
+        mode,
+        group: (group != 0).then_some(group),
+    })
+}
+
+// Proto3 string kosong = tidak diisi
+fn parse_client_order_id(client_order_id: String) -> Option<String> {
+    (!client_order_id.is_empty()).then_some(client_order_id)
+}
+
+// Setiap penolakan engine punya status code sendiri, reject reason-nya ikut sebagai google.rpc.ErrorInfo
+fn engine_error_to_status(error: EngineError) -> Status {
+    let reason = error.reason();
+    let code = match reason {
+        EngineRejectReason::InvalidPrice
+        | EngineRejectReason::PriceNotOnTick
+        | EngineRejectReason::InvalidQuantity
+        | EngineRejectReason::QuantityNotOnLot
+        | EngineRejectReason::QuantityBelowMinimum
+        | EngineRejectReason::QuantityAboveMaximum
+        | EngineRejectReason::NotionalBelowMinimum => Code::InvalidArgument,
+        EngineRejectReason::InsufficientBalance => Code::FailedPrecondition,
+        EngineRejectReason::DuplicateOrderId
+        | EngineRejectReason::DuplicateClientOrderId => Code::AlreadyExists,
+        EngineRejectReason::OrderNotFound => Code::NotFound,
+        EngineRejectReason::NotOrderOwner => Code::PermissionDenied,
+        EngineRejectReason::MarketHalted => Code::Unavailable,
+    };
+
+    let info = rpc::ErrorInfo {
+        reason: reject_reason_to_proto(reason).as_str_name().to_string(),
+        domain: ERROR_DOMAIN.to_string(),
+        metadata: Default::default(),
+    };
+    let details = rpc::Status {
+        code: code as i32,
+        message: error.to_string(),
+        details: vec![prost_types::Any {
+            type_url: "type.googleapis.com/google.rpc.ErrorInfo".to_string(),
+            value: info.encode_to_vec(),
+        }],
+    };
+    Status::with_details(code, error.to_string(), details.encode_to_vec().into())
+}
+
+fn order_status_to_proto(status: OrderStatus) -> ProtoOrderStatus {
+    let side = match status.side {
+        EngineSide::Bid => ProtoSide::Bid,
+        EngineSide::Ask => ProtoSide::Ask,
+    };
+    let state = match status.state {
+        OrderState::Open => ProtoOrderState::Open,
+        OrderState::PendingTrigger => ProtoOrderState::PendingTrigger,
+        OrderState::Filled => ProtoOrderState::Filled,
+        OrderState::Cancelled => ProtoOrderState::Cancelled,
+    };
+
+    ProtoOrderStatus {
+        order_id: status.order_id,
+        user_id: status.user_id,
+        side: side as i32,
+        price: status.price.unwrap_or(0),
+        trigger_price: status.trigger_price.unwrap_or(0),
+        filled_quantity: status.filled_quantity,
+        remaining_quantity: status.remaining_quantity,
+        client_order_id: status.client_order_id.unwrap_or_default(),
+        state: state as i32,
+    }
+}
+
+// Nama alasan cancel untuk WebSocket feed
+fn cancel_reason_name(reason: CancelReason) -> &'static str {
+    match reason {
+        CancelReason::UserRequested => "USER_REQUESTED",
+        CancelReason::SelfTradePrevention => "SELF_TRADE_PREVENTION",
+        CancelReason::Admin => "ADMIN",
+    }
+}
+
+// Konversi alasan reject dari Engine ke Proto
+fn reject_reason_to_proto(reason: EngineRejectReason) -> ProtoRejectReason {
+    match reason {
+        EngineRejectReason::InvalidPrice => ProtoRejectReason::InvalidPrice,
+        EngineRejectReason::PriceNotOnTick => ProtoRejectReason::PriceNotOnTick,
+        EngineRejectReason::InvalidQuantity => ProtoRejectReason::InvalidQuantity,
+        EngineRejectReason::QuantityNotOnLot => ProtoRejectReason::QuantityNotOnLot,
+        EngineRejectReason::QuantityBelowMinimum => ProtoRejectReason::QuantityBelowMinimum,
+        EngineRejectReason::QuantityAboveMaximum => ProtoRejectReason::QuantityAboveMaximum,
+        EngineRejectReason::NotionalBelowMinimum => ProtoRejectReason::NotionalBelowMinimum,
+        EngineRejectReason::InsufficientBalance => ProtoRejectReason::InsufficientBalance,
+        EngineRejectReason::DuplicateOrderId => ProtoRejectReason::DuplicateOrderId,
+        EngineRejectReason::DuplicateClientOrderId => ProtoRejectReason::DuplicateClientOrderId,
+        EngineRejectReason::OrderNotFound => ProtoRejectReason::OrderNotFound,
+        EngineRejectReason::NotOrderOwner => ProtoRejectReason::NotOrderOwner,
+        EngineRejectReason::MarketHalted => ProtoRejectReason::MarketHalted,
+    }
+}
+
+// Konversi Event Engine ke Response Proto untuk deposit/withdrawal.
+// Tanpa event = journal id sudah pernah diterapkan (retry), dijawab sukses tanpa mengubah saldo lagi
+fn build_funds_response(journal_id: u64, events: Vec<EngineEvent>) -> FundsResponse {
+    match events.first() {
+        Some(EngineEvent::FundsDeposited { journal_id, .. }) => FundsResponse {
+            success: true,
+            message: "Deposit Accepted".to_string(),
+            journal_id: *journal_id,
+        },
+        Some(EngineEvent::FundsWithdrawn { journal_id, .. }) => FundsResponse {
+            success: true,
+            message: "Withdrawal Accepted".to_string(),
+            journal_id: *journal_id,
+        },
+        None => FundsResponse {
+            success: true,
+            message: "Already Journaled".to_string(),
+            journal_id,
+        },
+        _ => FundsResponse {
+            success: false,
+            message: "Request Ignored".to_string(),
+            journal_id: 0,
+        },
+    }
+}
+
+// Konversi Event Engine ke Response Proto untuk order yang baru masuk (Limit/Market)
+fn build_place_response(order_id: u64, events: Vec<EngineEvent>) -> PlaceOrderResponse {
+    let mut fills = Vec::new();
+    let mut success = false;
+    let mut unfilled_quantity = 0;
+    let mut resting_price = 0;
+    let mut message = None;
+
+    for event in events {
+        match event {
+            EngineEvent::OrderPlaced { id, price, .. } if id == order_id => {
+                success = true; // Order masuk book (Maker)
+                resting_price = price;
+            }
+            // Jika kita adalah taker, catat eksekusi ini
+            EngineEvent::TradeExecuted { maker_id, taker_id, price, quantity, maker_fee, taker_fee, .. } if taker_id == order_id => {
+                fills.push(TradeExecution {
+                    maker_order_id: maker_id,
+                    price,
+                    quantity,
+                    maker_fee,
+                    taker_fee,
+                });
+                success = true; // Terjadi trade (Taker)
+            }
+            EngineEvent::OrderUnfilled { id, quantity, .. } if id == order_id => {
+                unfilled_quantity = quantity;
+            }
+            EngineEvent::OrderKilled { id, quantity, .. } if id == order_id => {
+                unfilled_quantity = quantity;
+                message = Some("Order Killed (FOK)".to_string());
+            }
+            EngineEvent::StopPlaced { id, .. } if id == order_id => {
+                success = true;
+                message = Some("Stop Order Accepted".to_string());
+            }
+            EngineEvent::OrderExpired { id, quantity, .. } if id == order_id => {
+                unfilled_quantity = quantity;
+                message = Some("Order Expired (GTD)".to_string());
+            }
+            EngineEvent::OrderCancelled { id, quantity, reason, .. } if id == order_id => {
+                unfilled_quantity = quantity;
+                message = Some(match reason {
+                    CancelReason::SelfTradePrevention => "Order Cancelled by Self-Trade Prevention".to_string(),
+                    reason => format!("Order Cancelled: {:?}", reason),
+                });
+            }
+            EngineEvent::PostOnlyRejected { id, .. } if id == order_id => {
+                message = Some("Post-Only Rejected: order would cross the spread".to_string());
+            }
+            EngineEvent::PostOnlySlid { id, original_price, price, .. } if id == order_id => {
+                message = Some(format!("Post-Only Slid: price {} -> {}", original_price, price));
+            }
+            _ => {}
+        }
+    }
+
+    let message = message.unwrap_or_else(|| {
+        if !success && unfilled_quantity > 0 {
+            "No Liquidity".to_string()
+        } else if !success {
+            "Order Rejected".to_string()
+        } else if unfilled_quantity > 0 {
+            "Order Partially Filled".to_string()
+        } else {
+            "Order Processed".to_string()
+        }
+    });
+
+    PlaceOrderResponse {
+        success,
+        message,
+        fills,
+        unfilled_quantity,
+        resting_price,
+    }
+}
+
+// Query WebSocket: /ws?symbol=SOL_USDC hanya menerima event market tersebut
+#[derive(Debug, Deserialize)]
+struct FeedParams {
+    symbol: Option<String>,
 }
 
 // Handler WebSocket
 async fn ws_handler (
     ws: WebSocketUpgrade,
-    State(broadcast_tx): State<broadcast::Sender<EngineEvent>>,
+    Query(params): Query<FeedParams>,
+    State(broadcast_tx): State<broadcast::Sender<MarketEvent>>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, broadcast_tx))
+    ws.on_upgrade(|socket| handle_socket(socket, broadcast_tx, params.symbol))
 }
 
-async fn handle_socket(mut socket: WebSocket, broadcast_tx: broadcast::Sender<EngineEvent>) {
+async fn handle_socket(mut socket: WebSocket, broadcast_tx: broadcast::Sender<MarketEvent>, symbol: Option<String>) {
     // Subcribe ke channel broadcast
     let mut rx = broadcast_tx.subscribe();
 
-    while let Ok(event) = rx.recv().await {
+    while let Ok(MarketEvent { symbol: market, event }) = rx.recv().await {
+        // Filter market (tanpa filter = semua market)
+        if symbol.as_ref().is_some_and(|s| *s != market) {
+            continue;
+        }
+
         // Konversi EngineEvent ke JSON
-        let json_msg = match event {
-            EngineEvent::TradeExecuted { maker_id, taker_id, price, quantity } => serde_json::json! ({
+        let mut json_msg = match event {
+            EngineEvent::TradeExecuted { maker_id, taker_id, price, quantity, maker_fee, taker_fee, .. } => serde_json::json! ({
                 "type": "TRADE",
                 "maker_id": maker_id,
                 "taker_id": taker_id,
                 "price": price,
                 "quantity": quantity,
+                "maker_fee": maker_fee,
+                "taker_fee": taker_fee,
             }),
             EngineEvent::OrderPlaced { id, price, quantity, side, ..  } => serde_json::json! ({
                 "type": "ORDER_PLACED",
@@ -196,12 +819,129 @@ async fn handle_socket(mut socket: WebSocket, broadcast_tx: broadcast::Sender<En
                 "quantity": quantity,
                 "side": format!("{:?}", side),
             }),
-            EngineEvent::OrderCancelled { id } => serde_json::json! ({
+            // Feed publik: reserve iceberg tidak boleh bocor, sisa penuh hanya ada di balasan gRPC pemilik order
+            EngineEvent::OrderCancelled { id, user_id, side, price, displayed_quantity, reason, .. } => serde_json::json! ({
                 "type": "ORDER_CANCELLED",
                 "id": id,
+                "user_id": user_id,
+                "side": format!("{:?}", side),
+                "price": price,
+                "remaining_quantity": displayed_quantity,
+                "reason": cancel_reason_name(reason),
+            }),
+            EngineEvent::OrderUnfilled { id, quantity, side, .. } => serde_json::json! ({
+                "type": "ORDER_UNFILLED",
+                "id": id,
+                "quantity": quantity,
+                "side": format!("{:?}", side),
+            }),
+            EngineEvent::OrderKilled { id, quantity, side, .. } => serde_json::json! ({
+                "type": "ORDER_KILLED",
+                "id": id,
+                "quantity": quantity,
+                "side": format!("{:?}", side),
+            }),
+            // Sama seperti ORDER_CANCELLED: reserve iceberg tidak ikut dipublikasikan
+            EngineEvent::OrderExpired { id, user_id, side, price, displayed_quantity, .. } => serde_json::json! ({
+                "type": "ORDER_EXPIRED",
+                "id": id,
+                "user_id": user_id,
+                "side": format!("{:?}", side),
+                "price": price,
+                "remaining_quantity": displayed_quantity,
+            }),
+            EngineEvent::OrderReplenished { id, price, quantity, side } => serde_json::json! ({
+                "type": "ORDER_REPLENISHED",
+                "id": id,
+                "price": price,
+                "quantity": quantity,
+                "side": format!("{:?}", side),
+            }),
+            EngineEvent::OrderAmended { id, price, quantity, side, priority_kept, .. } => serde_json::json! ({
+                "type": "ORDER_AMENDED",
+                "id": id,
+                "price": price,
+                "quantity": quantity,
+                "side": format!("{:?}", side),
+                "priority_kept": priority_kept,
+            }),
+            EngineEvent::StopPlaced { id, side, trigger_price, limit_price, quantity, .. } => serde_json::json! ({
+                "type": "STOP_PLACED",
+                "id": id,
+                "trigger_price": trigger_price,
+                "limit_price": limit_price,
+                "quantity": quantity,
+                "side": format!("{:?}", side),
+            }),
+            EngineEvent::StopTriggered { id, side, trigger_price, .. } => serde_json::json! ({
+                "type": "STOP_TRIGGERED",
+                "id": id,
+                "trigger_price": trigger_price,
+                "side": format!("{:?}", side),
+            }),
+            EngineEvent::StopCancelled { id, user_id, side, quantity, reason } => serde_json::json! ({
+                "type": "STOP_CANCELLED",
+                "id": id,
+                "user_id": user_id,
+                "side": format!("{:?}", side),
+                "remaining_quantity": quantity,
+                "reason": cancel_reason_name(reason),
+            }),
+            EngineEvent::MassCancelled { user_id, count } => serde_json::json! ({
+                "type": "MASS_CANCELLED",
+                "user_id": user_id,
+                "count": count,
+            }),
+            EngineEvent::SelfTradeDecremented { maker_id, taker_id, quantity } => serde_json::json! ({
+                "type": "SELF_TRADE_DECREMENTED",
+                "maker_id": maker_id,
+                "taker_id": taker_id,
+                "quantity": quantity,
+            }),
+            EngineEvent::PostOnlyRejected { id, price, side, .. } => serde_json::json! ({
+                "type": "POST_ONLY_REJECTED",
+                "id": id,
+                "price": price,
+                "side": format!("{:?}", side),
+            }),
+            EngineEvent::PostOnlySlid { id, original_price, price, side, .. } => serde_json::json! ({
+                "type": "POST_ONLY_SLID",
+                "id": id,
+                "original_price": original_price,
+                "price": price,
+                "side": format!("{:?}", side),
+            }),
+            EngineEvent::OrderRejected { id, reason, .. } => serde_json::json! ({
+                "type": "ORDER_REJECTED",
+                "id": id,
+                "reason": reject_reason_to_proto(reason).as_str_name(),
+                "message": reason.to_string(),
+            }),
+            EngineEvent::FundsDeposited { journal_id, user_id, asset, amount } => serde_json::json! ({
+                "type": "FUNDS_DEPOSITED",
+                "journal_id": journal_id,
+                "user_id": user_id,
+                "asset": format!("{:?}", asset),
+                "amount": amount,
+            }),
+            EngineEvent::FundsWithdrawn { journal_id, user_id, asset, amount } => serde_json::json! ({
+                "type": "FUNDS_WITHDRAWN",
+                "journal_id": journal_id,
+                "user_id": user_id,
+                "asset": format!("{:?}", asset),
+                "amount": amount,
+            }),
+            EngineEvent::WithdrawalRejected { user_id, asset, amount, .. } => serde_json::json! ({
+                "type": "WITHDRAWAL_REJECTED",
+                "user_id": user_id,
+                "asset": format!("{:?}", asset),
+                "amount": amount,
             }),
         };
 
+        // Setiap pesan ditandai symbol market asalnya
+        json_msg["symbol"] = serde_json::Value::String(market);
+
         // Kirim string JSON ke Client WebSocket
         if let Ok(msg_text) = serde_json::to_string(&json_msg) {
             if socket.send(Message::Text(msg_text)).await.is_err() {
@@ -211,19 +951,58 @@ async fn handle_socket(mut socket: WebSocket, broadcast_tx: broadcast::Sender<En
     }
 }
 
+// Baca basis points dari env (tidak di-set = 0)
+fn env_bps(name: &str) -> i64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(0)
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // 1. Setup Channel: Buffer 1024 command antrian
-    let (tx, rx) = mpsc::channel(1024);
-    // Channel Broadcast: kapasitas 100 pesan. Jika client lambat, pesan lama didrop (lag).
+    // 1. Setup Channel Broadcast: kapasitas 100 pesan. Jika client lambat, pesan lama didrop (lag).
+    // Satu channel untuk semua market, setiap event membawa symbol-nya
     let (broadcast_tx, _) = broadcast::channel(100);
 
-    // 2. Spawn Market Processor (The Engine) di background thread
-    let processor_broadcast_tx = broadcast_tx.clone();
-    let processor = MarketProcessor::new(rx, processor_broadcast_tx);
-    tokio::spawn(async move {
-        processor.run().await;
-    });
+    // 2. Spawn satu Market Processor (The Engine) per symbol, e.g. VELOCITY_MARKETS=SOL_USDC,BTC_USDC
+    let market_list = std::env::var("VELOCITY_MARKETS").unwrap_or_else(|_| DEFAULT_MARKETS.to_string());
+    // Fee maker/taker (bps) untuk semua market, e.g. VELOCITY_MAKER_FEE_BPS=-2 (rebate) VELOCITY_TAKER_FEE_BPS=5
+    let fees = FeeSchedule::new(env_bps("VELOCITY_MAKER_FEE_BPS"), env_bps("VELOCITY_TAKER_FEE_BPS"));
+    if !fees.is_valid() {
+        return Err(format!("Invalid fee schedule: {:?}", fees).into());
+    }
+
+    // Durability WAL: fsync (default) | group | flush, e.g. VELOCITY_WAL_DURABILITY=group
+    let durability = match std::env::var("VELOCITY_WAL_DURABILITY").as_deref() {
+        Err(_) | Ok("fsync") => Durability::Fsync,
+        Ok("group") => Durability::group_commit(),
+        Ok("flush") => Durability::FlushOnly,
+        Ok(other) => return Err(format!("Invalid VELOCITY_WAL_DURABILITY: {}", other).into()),
+    };
+
+    // Segment WAL yang sudah tercakup snapshot diarsipkan ke <dir>/<SYMBOL> jika di-set, selain itu dihapus
+    let wal_archive = std::env::var("VELOCITY_WAL_ARCHIVE_DIR").ok();
+
+    // WAL single-market versi lama tidak di-replay ke market mana pun: startup ditolak sampai file dipindahkan
+    registry::check_legacy_wal(registry::LEGACY_WAL_PATH)?;
+
+    let mut markets = MarketRegistry::new();
+    for symbol in market_list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        // Spec per market, e.g. VELOCITY_SPEC_SOL_USDC="tick=5,lot=10,min_notional=1000,base_decimals=9,quote_decimals=6".
+        // Tanpa env ini market memakai spec default (tanpa batasan tick/lot)
+        let spec = match std::env::var(format!("VELOCITY_SPEC_{}", symbol)) {
+            Ok(value) => value.parse::<InstrumentSpec>().map_err(|e| format!("Invalid VELOCITY_SPEC_{}: {}", symbol, e))?,
+            Err(_) => InstrumentSpec::default(),
+        };
+        let mut config = MarketConfig::new(symbol)
+            .with_spec(spec)
+            .with_fees(fees.clone())
+            .with_durability(durability);
+        if let Some(dir) = &wal_archive {
+            config = config.with_wal_archive(&format!("{}/{}", dir, symbol));
+        }
+        markets.spawn(config, broadcast_tx.clone())
+            .map_err(|e| format!("Failed to recover market {}: {}", symbol, e))?;
+    }
+    println!("Markets: {}", markets.symbols().collect::<Vec<_>>().join(", "));
 
     // 3. Setup WebSocket Server (Axum)
     // Berjalan di port terpisah: 3000
@@ -242,9 +1021,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // 4. Setup gRPC Server (Main Task)
     let addr = "[::1]:50051".parse()?;
-    let trading_service = TradingService {
-        processor_sender: tx,
-    };
+    let trading_service = TradingService { markets };
 
     println!("Velocity DEX Engine listening on {}", addr);
 
@@ -3,17 +3,25 @@
 use clap::Parser;
 use rand::Rng;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::Barrier;
 use tonic::transport::Channel;
 use trading::trading_engine_client::TradingEngineClient;
-use trading::{PlaceOrderRequest, Side};
+use trading::{PlaceOrderRequest, FundsRequest, Side, Asset};
 use hdrhistogram::Histogram;
+use engine_core::{LogEntry, Side as EngineSide};
+use engine_core::wal::{Durability, SegmentPolicy, WalHandler, WalRecovery};
 
+// Kode hasil generate dari proto, komentar proto ikut menjadi doc comment
+#[allow(clippy::doc_lazy_continuation)]
 pub mod trading {
     tonic::include_proto!("trading");
 }
 
+// User virtual yang dipakai benchmark dan saldo awal masing-masing aset
+const USER_IDS: std::ops::Range<u64> = 1..1000;
+const FUNDING_AMOUNT: u64 = 1_000_000_000;
+
 #[derive(Parser, Debug)]
 #[command(name = "Velocity Bencmark")]
 struct Args {
@@ -22,20 +30,32 @@ struct Args {
     count: usize,
 
     // Jumlah koneksi concurrent (Virtual Users)
-    #[arg(short, long, default_value_t = 50)]
+    #[arg(short = 'n', long, default_value_t = 50)]
     concurrency: usize,
 
     // URL Server gRPC
     #[arg(short, long, default_value = "http://[::1]:50051")]
     url: String,
+
+    // Market yang dibanjiri order
+    #[arg(short, long, default_value = "SOL_USDC")]
+    symbol: String,
+
+    // Benchmark WAL lokal (tanpa server): latency sampai entry durable untuk setiap durability policy
+    #[arg(long)]
+    wal: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
+    if args.wal {
+        return wal_benchmark(args.count);
+    }
+
     println!("Starting Benchmark: {} orders | {} users", args.count, args.concurrency);
-    println!("Target: {}", args.url);
+    println!("Target: {} ({})", args.url, args.symbol);
 
     // 1. Setup koneksi (channel pool sederhana)
     let mut channels = Vec::new();
@@ -46,6 +66,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         channels.push(channel);
     }
 
+    // Isi saldo semua user virtual agar order tidak ditolak karena Insufficient Balance
+    let mut funding = TradingEngineClient::new(channels[0].clone());
+    for user_id in USER_IDS {
+        for asset in [Asset::Base, Asset::Quote] {
+            funding.deposit(FundsRequest {
+                user_id,
+                asset: asset as i32,
+                amount: FUNDING_AMOUNT,
+                symbol: args.symbol.clone(),
+                // Acak agar run berikutnya tidak dianggap retry deposit yang sama
+                journal_id: rand::rng().random(),
+            }).await?;
+        }
+    }
+
     let orders_per_user = args.count / args.concurrency;
     let barrier = Arc::new(Barrier::new(args.concurrency));
     let mut handles = Vec::new();
@@ -53,10 +88,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let start_time = Instant::now();
 
     // 2. Spawn Virtual Users
-    for i in 0..args.concurrency {
-        let channel = channels[i].clone();
+    for channel in channels {
         let barrier = barrier.clone();
         let count = orders_per_user;
+        let symbol = args.symbol.clone();
 
         let handle = tokio::spawn(async move {
             let mut client = TradingEngineClient::new(channel);
@@ -75,7 +110,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         if rng.random_bool(0.5) { Side::Bid } else { Side::Ask },
                         rng.random_range(90..110),
                         rng.random_range(1..100),
-                        rng.random_range(1..1000),
+                        rng.random_range(USER_IDS),
                         rng.random::<u64>()
                     )
                 };
@@ -86,6 +121,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     side: side as i32,
                     price,
                     quantity,
+                    symbol: symbol.clone(),
+                    ..Default::default()
                 };
 
                 let start = Instant::now();
@@ -109,12 +146,82 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    let total_duration = start_time.elapsed();
-    let throughput = args.count as f64 / total_duration.as_secs_f64();
-
     // 4. Print Report (The "Money Shot")
+    print_report("BENCHMARK COMPLETE", args.count, start_time.elapsed(), &total_hist);
+
+    Ok(())
+}
+
+// Tulis `count` entry ke WAL sementara dengan setiap policy. Latency dihitung dari write sampai entry durable
+// (untuk group commit: sampai batch-nya di-sync, sama seperti ack di MarketProcessor)
+fn wal_benchmark(count: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let policies = [
+        ("FSYNC PER ENTRY", Durability::Fsync),
+        ("GROUP COMMIT", Durability::group_commit()),
+        ("FLUSH ONLY", Durability::FlushOnly),
+    ];
+
+    for (name, durability) in policies {
+        let dir = std::env::temp_dir().join(format!("velocity-bench-wal-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut wal = WalHandler::open(&dir, &WalRecovery::default(), SegmentPolicy::default(), durability)?;
+        let max_delay = match durability {
+            Durability::GroupCommit { max_delay, .. } => max_delay,
+            _ => Duration::ZERO,
+        };
+
+        let mut hist = Histogram::<u64>::new(3).unwrap();
+        // Waktu mulai entry yang menunggu batch-nya durable
+        let mut batch: Vec<Instant> = Vec::new();
+        let start_time = Instant::now();
+
+        for order_id in 1..=count as u64 {
+            let entry = {
+                let mut rng = rand::rng();
+                LogEntry::Place {
+                    order_id,
+                    user_id: rng.random_range(USER_IDS),
+                    side: if rng.random_bool(0.5) { EngineSide::Bid } else { EngineSide::Ask },
+                    price: rng.random_range(90..110),
+                    quantity: rng.random_range(1..100),
+                    time_in_force: Default::default(),
+                    post_only: Default::default(),
+                    stp: Default::default(),
+                    display_quantity: None,
+                    client_order_id: None,
+                    timestamp: 0,
+                }
+            };
+
+            batch.push(Instant::now());
+            wal.write_entry(&entry)?;
+
+            let batch_due = batch.first().is_some_and(|first| first.elapsed() >= max_delay);
+            if wal.unsynced() == 0 || wal.batch_full() || batch_due {
+                wal.sync()?;
+                for start in batch.drain(..) {
+                    hist += start.elapsed().as_micros() as u64;
+                }
+            }
+        }
+        wal.sync()?;
+        for start in batch.drain(..) {
+            hist += start.elapsed().as_micros() as u64;
+        }
+
+        print_report(&format!("WAL {}", name), count, start_time.elapsed(), &hist);
+        drop(wal);
+        std::fs::remove_dir_all(&dir)?;
+    }
+
+    Ok(())
+}
+
+fn print_report(title: &str, count: usize, total_duration: Duration, total_hist: &Histogram<u64>) {
+    let throughput = count as f64 / total_duration.as_secs_f64();
+
     println!("\n========================================");
-    println!("BENCHMARK COMPLETE");
+    println!("{}", title);
     println!("========================================");
     println!("Total Time     : {:.2?}", total_duration);
     println!("Throughput     : {:.2} Orders/sec", throughput);
@@ -127,6 +234,4 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("   p99            : {} us", total_hist.value_at_quantile(0.99));
     println!("   Max            : {} us", total_hist.max());
     println!("========================================");
-
-    Ok(())
 }
\ No newline at end of file
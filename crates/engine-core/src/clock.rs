@@ -0,0 +1,52 @@
+// crates/engine-core/src/clock.rs
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Sumber waktu engine (milidetik sejak UNIX epoch).
+// Hanya dibaca oleh MarketProcessor saat menulis entry WAL, OrderBook sendiri
+// selalu memakai timestamp dari log agar replay tetap deterministic
+pub trait Clock: Send {
+    fn now(&self) -> u64;
+}
+
+// Jam dinding sistem, dipakai di production
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+// Jam manual untuk test dan simulasi. Clone berbagi waktu yang sama,
+// jadi test bisa memajukan waktu setelah clock diserahkan ke processor
+#[derive(Debug, Default, Clone)]
+pub struct ManualClock {
+    now: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new(start: u64) -> Self {
+        Self { now: Arc::new(AtomicU64::new(start)) }
+    }
+
+    pub fn set(&self, timestamp: u64) {
+        self.now.store(timestamp, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, millis: u64) {
+        self.now.fetch_add(millis, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
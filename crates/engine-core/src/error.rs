@@ -0,0 +1,42 @@
+// crates/engine-core/src/error.rs
+
+use thiserror::Error;
+use crate::{EngineEvent, OrderId, RejectReason};
+use crate::ledger::Asset;
+
+// Kegagalan request ke engine. Setiap kasus punya RejectReason agar client bisa bereaksi secara programatik
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum EngineError {
+    #[error("order {order_id} rejected: {reason}")]
+    OrderRejected { order_id: OrderId, reason: RejectReason },
+    #[error("withdrawal of {amount} {asset:?} rejected: only {available} available")]
+    WithdrawalRejected { asset: Asset, amount: u64, available: u64 },
+    // Query order yang tidak dikenal (atau milik user lain)
+    #[error("order {order_id} not found")]
+    OrderNotFound { order_id: OrderId },
+    // WAL gagal ditulis, market read-only sampai operator resume
+    #[error("market is halted: write-ahead log unavailable")]
+    MarketHalted,
+}
+
+impl EngineError {
+    pub fn reason(&self) -> RejectReason {
+        match *self {
+            EngineError::OrderRejected { reason, .. } => reason,
+            EngineError::WithdrawalRejected { .. } => RejectReason::InsufficientBalance,
+            EngineError::OrderNotFound { .. } => RejectReason::OrderNotFound,
+            EngineError::MarketHalted => RejectReason::MarketHalted,
+        }
+    }
+
+    // Penolakan yang dilaporkan di event hasil satu command (None = request diterima)
+    pub fn from_events(events: &[EngineEvent]) -> Option<Self> {
+        events.iter().find_map(|event| match *event {
+            EngineEvent::OrderRejected { id, reason, .. } => Some(EngineError::OrderRejected { order_id: id, reason }),
+            EngineEvent::WithdrawalRejected { asset, amount, available, .. } => {
+                Some(EngineError::WithdrawalRejected { asset, amount, available })
+            }
+            _ => None,
+        })
+    }
+}
@@ -0,0 +1,174 @@
+// crates/engine-core/src/fees.rs
+
+use std::collections::{HashMap, VecDeque};
+use serde::{Serialize, Deserialize};
+use crate::{EngineEvent, UserId};
+
+// 1 bps = 0.01%
+const BPS_DENOMINATOR: i128 = 10_000;
+
+// Tarif untuk user dengan rolling volume >= min_volume (dalam quote asset)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeTier {
+    pub min_volume: u128,
+    pub maker_bps: i64,
+    pub taker_bps: i64,
+}
+
+// Fee schedule satu market. Fee selalu dalam quote asset.
+// maker_bps negatif = rebate untuk maker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeSchedule {
+    pub maker_bps: i64,
+    pub taker_bps: i64,
+    // Tier berdasarkan volume, tier dengan min_volume tertinggi yang terpenuhi yang dipakai
+    pub tiers: Vec<FeeTier>,
+    // Panjang jendela rolling volume (ms waktu engine)
+    pub volume_window_ms: u64,
+}
+
+impl Default for FeeSchedule {
+    // Tanpa fee
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+impl FeeSchedule {
+    // Default jendela volume: 30 hari
+    pub fn new(maker_bps: i64, taker_bps: i64) -> Self {
+        Self {
+            maker_bps,
+            taker_bps,
+            tiers: Vec::new(),
+            volume_window_ms: 30 * 24 * 60 * 60 * 1000,
+        }
+    }
+
+    pub fn with_tier(mut self, tier: FeeTier) -> Self {
+        self.tiers.push(tier);
+        self.tiers.sort_by_key(|t| t.min_volume);
+        self
+    }
+
+    // Taker selalu membayar, rebate maker tidak boleh melebihi fee taker (exchange tidak pernah rugi)
+    pub fn is_valid(&self) -> bool {
+        let mut rates = std::iter::once((self.maker_bps, self.taker_bps))
+            .chain(self.tiers.iter().map(|t| (t.maker_bps, t.taker_bps)));
+
+        rates.all(|(maker, taker)| {
+            (0..10_000).contains(&taker) && maker < 10_000 && maker + taker >= 0
+        })
+    }
+
+    // Tarif (maker, taker) untuk rolling volume tertentu
+    pub fn rates(&self, volume: u128) -> (i64, i64) {
+        self.tiers
+            .iter()
+            .rev()
+            .find(|t| volume >= t.min_volume)
+            .map_or((self.maker_bps, self.taker_bps), |t| (t.maker_bps, t.taker_bps))
+    }
+
+    // Tarif tertinggi yang mungkin dikenakan, dipakai ledger untuk mengunci dana fee di muka
+    pub fn max_bps(&self) -> i64 {
+        self.tiers
+            .iter()
+            .flat_map(|t| [t.maker_bps, t.taker_bps])
+            .chain([self.maker_bps, self.taker_bps, 0])
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Notional + fee maksimum (dibulatkan ke atas)
+    pub fn with_max_fee(&self, notional: u128) -> u128 {
+        let bps = self.max_bps() as u128;
+        notional + (notional * bps).div_ceil(BPS_DENOMINATOR as u128)
+    }
+
+    // Kebalikan dari with_max_fee: notional terbesar yang fee-nya masih tertutup `amount`
+    pub fn notional_budget(&self, amount: u64) -> u64 {
+        let bps = self.max_bps() as u128;
+        let denominator = BPS_DENOMINATOR as u128;
+        (amount as u128 * denominator / (denominator + bps)) as u64
+    }
+}
+
+// Fee satu sisi trade (dibulatkan ke bawah, rebate juga)
+fn fee(notional: u128, bps: i64) -> i64 {
+    (notional as i128 * bps as i128 / BPS_DENOMINATOR) as i64
+}
+
+// Menghitung fee setiap trade dan melacak rolling volume per user.
+// Waktu diambil dari engine (WAL), jadi hasilnya identik saat replay.
+// Snapshot hanya menyimpan rolling volume, schedule selalu dari konfigurasi market
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FeeEngine {
+    #[serde(skip)]
+    schedule: FeeSchedule,
+    // User -> (timestamp, notional) trade dalam jendela volume, beserta totalnya
+    volumes: HashMap<UserId, (VecDeque<(u64, u128)>, u128)>,
+}
+
+impl FeeEngine {
+    pub fn new(schedule: FeeSchedule) -> Self {
+        Self { schedule, volumes: HashMap::new() }
+    }
+
+    pub fn schedule(&self) -> &FeeSchedule {
+        &self.schedule
+    }
+
+    pub fn set_schedule(&mut self, schedule: FeeSchedule) {
+        self.schedule = schedule;
+    }
+
+    // Rolling volume user (quote asset) pada waktu engine `now`
+    pub fn volume(&mut self, user_id: UserId, now: u64) -> u128 {
+        let window_start = now.saturating_sub(self.schedule.volume_window_ms);
+        let Some((trades, total)) = self.volumes.get_mut(&user_id) else {
+            return 0;
+        };
+
+        while let Some(&(timestamp, notional)) = trades.front() {
+            if timestamp >= window_start {
+                break;
+            }
+            trades.pop_front();
+            *total -= notional;
+        }
+
+        *total
+    }
+
+    // Isi maker_fee/taker_fee pada setiap TradeExecuted, lalu tambahkan volume trade tersebut.
+    // Tier ditentukan dari volume sebelum trade
+    pub fn charge(&mut self, events: &mut [EngineEvent], now: u64) {
+        for event in events.iter_mut() {
+            if let EngineEvent::TradeExecuted {
+                maker_user_id, taker_user_id, price, quantity, maker_fee, taker_fee, ..
+            } = event {
+                let notional = *price as u128 * *quantity as u128;
+
+                let maker_volume = self.volume(*maker_user_id, now);
+                let taker_volume = self.volume(*taker_user_id, now);
+                let (maker_bps, _) = self.schedule.rates(maker_volume);
+                let (_, taker_bps) = self.schedule.rates(taker_volume);
+                *maker_fee = fee(notional, maker_bps);
+                *taker_fee = fee(notional, taker_bps);
+
+                self.record(*maker_user_id, now, notional);
+                self.record(*taker_user_id, now, notional);
+            }
+        }
+    }
+
+    fn record(&mut self, user_id: UserId, now: u64, notional: u128) {
+        let (trades, total) = self.volumes.entry(user_id).or_default();
+        match trades.back_mut() {
+            Some((timestamp, volume)) if *timestamp == now => *volume += notional,
+            _ => trades.push_back((now, notional)),
+        }
+        *total += notional;
+    }
+}
@@ -0,0 +1,116 @@
+// crates/engine-core/src/instrument.rs
+
+use std::str::FromStr;
+use crate::{Price, Quantity, RejectReason};
+
+// Reference data satu instrument. Semua angka dalam atomic units,
+// `base_decimals`/`quote_decimals` hanya untuk konversi tampilan di client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstrumentSpec {
+    // Kelipatan harga yang valid
+    pub tick_size: Price,
+    // Kelipatan quantity yang valid
+    pub lot_size: Quantity,
+    pub min_quantity: Quantity,
+    pub max_quantity: Quantity,
+    // Minimal price * quantity (dalam quote asset)
+    pub min_notional: u128,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+}
+
+impl Default for InstrumentSpec {
+    // Spec paling longgar: semua harga/quantity > 0 valid
+    fn default() -> Self {
+        Self {
+            tick_size: 1,
+            lot_size: 1,
+            min_quantity: 1,
+            max_quantity: Quantity::MAX,
+            min_notional: 0,
+            base_decimals: 0,
+            quote_decimals: 0,
+        }
+    }
+}
+
+impl InstrumentSpec {
+    // Validasi limit order (harga + quantity + notional)
+    pub fn validate_limit(&self, price: Price, quantity: Quantity) -> Result<(), RejectReason> {
+        self.validate_price(price)?;
+        self.validate_quantity(quantity)?;
+
+        if (price as u128) * (quantity as u128) < self.min_notional {
+            return Err(RejectReason::NotionalBelowMinimum);
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_price(&self, price: Price) -> Result<(), RejectReason> {
+        if price == 0 {
+            return Err(RejectReason::InvalidPrice);
+        }
+        if !price.is_multiple_of(self.tick_size) {
+            return Err(RejectReason::PriceNotOnTick);
+        }
+        Ok(())
+    }
+
+    // Market order hanya bisa dicek quantity-nya (harga eksekusi belum diketahui)
+    pub fn validate_quantity(&self, quantity: Quantity) -> Result<(), RejectReason> {
+        if quantity == 0 {
+            return Err(RejectReason::InvalidQuantity);
+        }
+        if !quantity.is_multiple_of(self.lot_size) {
+            return Err(RejectReason::QuantityNotOnLot);
+        }
+        if quantity < self.min_quantity {
+            return Err(RejectReason::QuantityBelowMinimum);
+        }
+        if quantity > self.max_quantity {
+            return Err(RejectReason::QuantityAboveMaximum);
+        }
+        Ok(())
+    }
+
+    // Iceberg: slice yang terlihat juga harus kelipatan lot
+    pub fn validate_display_quantity(&self, display_quantity: Quantity) -> Result<(), RejectReason> {
+        if !display_quantity.is_multiple_of(self.lot_size) {
+            return Err(RejectReason::QuantityNotOnLot);
+        }
+        Ok(())
+    }
+}
+
+// Format config "tick=5,lot=10,min_qty=10,max_qty=100000,min_notional=1000,base_decimals=9,quote_decimals=6".
+// Key yang tidak disebut memakai nilai default
+impl FromStr for InstrumentSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut spec = InstrumentSpec::default();
+        for field in s.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let (key, value) = field.split_once('=').ok_or_else(|| format!("expected key=value, got {:?}", field))?;
+            let invalid = |_| format!("invalid value for {}: {:?}", key, value);
+            match key.trim() {
+                "tick" => spec.tick_size = value.trim().parse().map_err(invalid)?,
+                "lot" => spec.lot_size = value.trim().parse().map_err(invalid)?,
+                "min_qty" => spec.min_quantity = value.trim().parse().map_err(invalid)?,
+                "max_qty" => spec.max_quantity = value.trim().parse().map_err(invalid)?,
+                "min_notional" => spec.min_notional = value.trim().parse().map_err(invalid)?,
+                "base_decimals" => spec.base_decimals = value.trim().parse().map_err(invalid)?,
+                "quote_decimals" => spec.quote_decimals = value.trim().parse().map_err(invalid)?,
+                other => return Err(format!("unknown instrument field {:?}", other)),
+            }
+        }
+
+        if spec.tick_size == 0 || spec.lot_size == 0 {
+            return Err("tick and lot must be non-zero".to_string());
+        }
+        if spec.min_quantity > spec.max_quantity {
+            return Err("min_qty is above max_qty".to_string());
+        }
+        Ok(spec)
+    }
+}
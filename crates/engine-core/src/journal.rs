@@ -0,0 +1,448 @@
+// crates/engine-core/src/journal.rs
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use crate::{EngineEvent, LogEntry, MATCHING_VERSION};
+use crate::wal::{self, WalError};
+
+// Journal output engine (append-only): satu record untuk setiap entry WAL, termasuk entry tanpa event,
+// berisi event hasil eksekusinya. Settlement, reporting dan replay market data membaca file ini
+// tanpa menjalankan matcher lagi.
+//
+// Header file (little endian): [magic: 8 byte "VDEXJRN\0"][format version: u32][matching version: u32]
+// Record: [len: u32][crc32: u32][input seq: u64][payload: bincode(JournalBody)], framing sama dengan WAL
+pub const JOURNAL_FORMAT_VERSION: u32 = 1;
+const MAGIC: &[u8; 8] = b"VDEXJRN\0";
+const FILE_HEADER_LEN: usize = 16;
+
+// Event dari satu entry WAL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalRecord {
+    // Seq entry WAL yang menghasilkan event ini (berurutan tanpa celah, sama dengan WAL)
+    pub input_seq: u64,
+    // crc32 entry WAL tersebut, untuk mencocokkan journal dengan WAL tanpa replay
+    pub input_crc: u32,
+    // Event ke-i punya seq first_event_seq + i. Seq event berurutan tanpa celah di seluruh journal
+    pub first_event_seq: u64,
+    pub events: Vec<EngineEvent>,
+}
+
+impl JournalRecord {
+    // Record ini identik dengan hasil eksekusi ulang `entry`
+    pub fn matches(&self, entry: &LogEntry, events: &[EngineEvent]) -> bool {
+        self.input_crc == input_checksum(entry)
+            && bincode::serialize(&self.events).ok() == bincode::serialize(events).ok()
+    }
+}
+
+#[derive(Deserialize)]
+struct JournalBody {
+    input_crc: u32,
+    first_event_seq: u64,
+    events: Vec<EngineEvent>,
+}
+
+// Versi pinjaman dari JournalBody untuk ditulis tanpa clone event (urutan field harus sama)
+#[derive(Serialize)]
+struct JournalBodyRef<'a> {
+    input_crc: u32,
+    first_event_seq: u64,
+    events: &'a [EngineEvent],
+}
+
+// Hasil membaca journal saat startup
+#[derive(Debug)]
+pub struct JournalRecovery {
+    // Record dengan input seq > after_seq (untuk diverifikasi terhadap replay WAL)
+    pub records: Vec<JournalRecord>,
+    // Input seq record terakhir yang dipertahankan (0 = journal kosong)
+    pub last_input_seq: u64,
+    pub next_event_seq: u64,
+    // Panjang prefix yang valid (termasuk header, 0 = file belum ada)
+    pub len: u64,
+    pub torn_bytes: u64,
+    // Record untuk entry yang tidak ada di WAL (entry tidak pernah durable), ikut dibuang
+    pub discarded: u64,
+}
+
+pub struct EventJournal {
+    path: PathBuf,
+    file: File,
+    // Panjang file yang sudah pasti tertulis utuh (batas truncate saat repair)
+    written: u64,
+    // Record yang sudah dicatat tapi belum ditulis ke file
+    pending: Vec<u8>,
+    last_input_seq: u64,
+    next_event_seq: u64,
+    // Write gagal: tidak ada write lagi sampai repair()
+    failed: bool,
+}
+
+impl EventJournal {
+    // Buka journal untuk ditulis lanjut dari akhir record valid (sisa record terpotong/dibuang di-truncate)
+    pub fn open(path: impl AsRef<Path>, recovery: &JournalRecovery) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        let written = match recovery.len {
+            0 => {
+                let mut file = OpenOptions::new().create(true).write(true).truncate(true).open(&path)?;
+                file.write_all(MAGIC)?;
+                file.write_all(&JOURNAL_FORMAT_VERSION.to_le_bytes())?;
+                file.write_all(&MATCHING_VERSION.to_le_bytes())?;
+                file.sync_data()?;
+                FILE_HEADER_LEN as u64
+            }
+            len => {
+                OpenOptions::new().write(true).open(&path)?.set_len(len)?;
+                len
+            }
+        };
+
+        Ok(Self {
+            file: OpenOptions::new().append(true).open(&path)?,
+            path,
+            written,
+            pending: Vec::new(),
+            last_input_seq: recovery.last_input_seq,
+            next_event_seq: recovery.next_event_seq,
+            failed: false,
+        })
+    }
+
+    // Catat event hasil entry WAL `input_seq`. Hanya di-buffer, ditulis ke file oleh flush()
+    pub fn append(&mut self, input_seq: u64, entry: &LogEntry, events: &[EngineEvent]) -> io::Result<()> {
+        debug_assert!(self.last_input_seq == 0 || input_seq == self.last_input_seq + 1, "journal must follow WAL order");
+        let payload = bincode::serialize(&JournalBodyRef {
+            input_crc: input_checksum(entry),
+            first_event_seq: self.next_event_seq,
+            events,
+        }).map_err(io::Error::other)?;
+
+        self.pending.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.pending.extend_from_slice(&wal::checksum(input_seq, &payload).to_le_bytes());
+        self.pending.extend_from_slice(&input_seq.to_le_bytes());
+        self.pending.extend_from_slice(&payload);
+
+        self.last_input_seq = input_seq;
+        self.next_event_seq += events.len() as u64;
+        Ok(())
+    }
+
+    // Tulis record yang tertunda ke file (tanpa fsync: journal bisa dibangun ulang dari WAL sesudah snapshot)
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other("event journal is halted after a failed write, repair required"));
+        }
+        if !self.pending.is_empty() {
+            if let Err(e) = self.file.write_all(&self.pending) {
+                self.failed = true;
+                return Err(e);
+            }
+            self.written += self.pending.len() as u64;
+            self.pending.clear();
+        }
+        Ok(())
+    }
+
+    // Flush + fsync. Wajib sebelum snapshot, karena event sebelum snapshot tidak bisa dibangun ulang
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.file.sync_data()
+    }
+
+    // Buang sisa record yang gagal ditulis, lalu tulis ulang record yang tertunda
+    pub fn repair(&mut self) -> io::Result<()> {
+        self.file.set_len(self.written)?;
+        self.failed = false;
+        self.sync()
+    }
+
+    pub fn last_input_seq(&self) -> u64 {
+        self.last_input_seq
+    }
+
+    pub fn next_event_seq(&self) -> u64 {
+        self.next_event_seq
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Membaca journal saat startup. Record dengan input seq > through_seq (sudah tidak ada di WAL) dibuang,
+    // record terakhir yang terpotong (torn write) dibuang, record rusak di tempat lain adalah error
+    pub fn recover(path: impl AsRef<Path>, after_seq: u64, through_seq: u64) -> Result<JournalRecovery, WalError> {
+        let path = path.as_ref();
+        let mut recovery = JournalRecovery {
+            records: Vec::new(),
+            last_input_seq: 0,
+            next_event_seq: 1,
+            len: 0,
+            torn_bytes: 0,
+            discarded: 0,
+        };
+
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(recovery),
+            Err(e) => return Err(e.into()),
+        };
+        if bytes.len() < FILE_HEADER_LEN {
+            // Crash sebelum header selesai ditulis: journal dianggap kosong
+            recovery.torn_bytes = bytes.len() as u64;
+            return Ok(recovery);
+        }
+        if &bytes[0..8] != MAGIC {
+            return Err(WalError::Corrupted { segment: path.to_path_buf(), offset: 0, detail: "not an event journal".to_string() });
+        }
+        let format_version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if format_version != JOURNAL_FORMAT_VERSION {
+            return Err(WalError::UnsupportedFormat {
+                segment: path.to_path_buf(),
+                found: format_version,
+                expected: JOURNAL_FORMAT_VERSION,
+            });
+        }
+
+        let mut offset = FILE_HEADER_LEN;
+        recovery.len = offset as u64;
+        // Input seq record terakhir yang terbaca (termasuk yang dibuang)
+        let mut previous_seq = None;
+        while offset < bytes.len() {
+            let rest = &bytes[offset..];
+            if rest.len() < wal::HEADER_LEN {
+                break;
+            }
+
+            let len = u32::from_le_bytes(rest[0..4].try_into().unwrap()) as usize;
+            let crc = u32::from_le_bytes(rest[4..8].try_into().unwrap());
+            let input_seq = u64::from_le_bytes(rest[8..16].try_into().unwrap());
+
+            if len > wal::MAX_RECORD_LEN {
+                wal::corrupted(path, rest, offset, format!("record length {} exceeds limit", len))?;
+                break;
+            }
+            if rest.len() < wal::HEADER_LEN + len {
+                let next_seq = previous_seq.map_or(input_seq, |seq| seq + 1) + 1;
+                wal::overrun(path, &bytes, offset, len, next_seq)?;
+                break;
+            }
+
+            let payload = &rest[wal::HEADER_LEN..wal::HEADER_LEN + len];
+            if wal::checksum(input_seq, payload) != crc {
+                wal::corrupted(path, rest, offset, format!("checksum mismatch in record for input seq {}", input_seq))?;
+                break;
+            }
+            let body: JournalBody = bincode::deserialize(payload).map_err(|e| WalError::Corrupted {
+                segment: path.to_path_buf(),
+                offset: offset as u64,
+                detail: format!("undecodable record: {}", e),
+            })?;
+            offset += wal::HEADER_LEN + len;
+            previous_seq = Some(input_seq);
+
+            // Sesudah record pertama yang dibuang, semua record berikutnya juga lebih baru dari WAL
+            if input_seq > through_seq || recovery.discarded > 0 {
+                recovery.discarded += 1;
+                continue;
+            }
+            if recovery.last_input_seq != 0 && input_seq != recovery.last_input_seq + 1 {
+                return Err(WalError::Corrupted {
+                    segment: path.to_path_buf(),
+                    offset: recovery.len,
+                    detail: format!("expected input seq {}, found {}", recovery.last_input_seq + 1, input_seq),
+                });
+            }
+            if body.first_event_seq != recovery.next_event_seq {
+                return Err(WalError::Corrupted {
+                    segment: path.to_path_buf(),
+                    offset: recovery.len,
+                    detail: format!("expected event seq {}, found {}", recovery.next_event_seq, body.first_event_seq),
+                });
+            }
+
+            recovery.last_input_seq = input_seq;
+            recovery.next_event_seq += body.events.len() as u64;
+            recovery.len = offset as u64;
+            if input_seq > after_seq {
+                recovery.records.push(JournalRecord {
+                    input_seq,
+                    input_crc: body.input_crc,
+                    first_event_seq: body.first_event_seq,
+                    events: body.events,
+                });
+            }
+        }
+        recovery.torn_bytes = (bytes.len() - offset) as u64;
+
+        Ok(recovery)
+    }
+
+    // Seluruh isi journal untuk konsumen downstream
+    pub fn read(path: impl AsRef<Path>) -> Result<Vec<JournalRecord>, WalError> {
+        Ok(Self::recover(path, 0, u64::MAX)?.records)
+    }
+}
+
+// Cocokkan journal dengan entry WAL yang masih ada (setelah compaction, WAL hanya mencakup sebagian journal).
+// Hanya memeriksa bahwa setiap record milik entry WAL yang benar (input_crc), isi event tidak diperiksa:
+// event diverifikasi byte-per-byte terhadap hasil replay saat startup MarketProcessor (record sesudah snapshot).
+// Mengembalikan jumlah record yang terverifikasi
+pub fn verify(records: &[JournalRecord], wal_entries: &[(u64, LogEntry)]) -> Result<usize, WalError> {
+    let Some(&(first_seq, _)) = wal_entries.first() else { return Ok(0) };
+
+    let mut verified = 0;
+    for record in records.iter().filter(|record| record.input_seq >= first_seq) {
+        let Some((_, entry)) = wal_entries.get((record.input_seq - first_seq) as usize) else { break };
+        if record.input_crc != input_checksum(entry) {
+            return Err(WalError::JournalMismatch {
+                input_seq: record.input_seq,
+                detail: "journal record does not belong to this WAL entry".to_string(),
+            });
+        }
+        verified += 1;
+    }
+    Ok(verified)
+}
+
+// crc32 dari entry WAL (bincode), sama untuk entry yang sama di WAL manapun
+pub fn input_checksum(entry: &LogEntry) -> u32 {
+    crc32fast::hash(&bincode::serialize(entry).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::{broadcast, mpsc, oneshot};
+    use crate::Side;
+    use crate::ledger::Asset;
+    use crate::processor::{Command, MarketProcessor};
+    use crate::test_support::{deposit_command, market_config, place_command, TempDir};
+    use crate::wal::WalHandler;
+
+    #[test]
+    fn test_journal_corrupted_length_field_is_not_a_torn_tail() {
+        let dir = TempDir::new("journal_length");
+        let path = dir.join("events.journal");
+
+        let mut journal = EventJournal::open(&path, &EventJournal::recover(&path, 0, 0).unwrap()).unwrap();
+        for seq in 1..=3 {
+            journal.append(seq, &LogEntry::Expire { timestamp: seq }, &[]).unwrap();
+        }
+        journal.sync().unwrap();
+        drop(journal);
+        let bytes = fs::read(&path).unwrap();
+        let record_len = (bytes.len() - FILE_HEADER_LEN) / 3;
+
+        // Length record tengah rusak: record sesudahnya masih utuh, jadi tidak boleh dipotong diam-diam
+        let middle = FILE_HEADER_LEN + record_len;
+        let mut corrupted = bytes.clone();
+        corrupted[middle + 1] ^= 0x10;
+        fs::write(&path, &corrupted).unwrap();
+        match EventJournal::recover(&path, 0, u64::MAX) {
+            Err(WalError::Corrupted { offset, .. }) => assert_eq!(offset, middle as u64),
+            other => panic!("Harusnya korupsi, dapat {:?}", other.map(|r| r.records.len())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_journal_matches_wal_and_is_rebuilt_after_crash() {
+        let dir = TempDir::new("journal");
+        let config = market_config(&dir).with_snapshot_interval(0);
+        let journal_path = PathBuf::from(&config.journal_path);
+
+        let (tx, rx) = mpsc::channel(8);
+        let (broadcast_tx, _) = broadcast::channel(16);
+        let processor = MarketProcessor::new(config.clone(), rx, broadcast_tx.clone()).unwrap();
+        let engine = tokio::spawn(processor.run());
+
+        let commands = [
+            deposit_command(1, 1, Asset::Base, 5),
+            deposit_command(2, 2, Asset::Quote, 1_000),
+            place_command(1, 1, Side::Ask, 10, 5),
+            place_command(2, 2, Side::Bid, 10, 5),
+        ];
+        for (command, reply) in commands {
+            tx.send(command).await.unwrap();
+            assert!(reply.await.unwrap().is_ok());
+        }
+        drop(tx);
+        engine.await.unwrap();
+
+        // Satu record per entry WAL, seq event berurutan, trade tercatat di record order taker
+        let records = EventJournal::read(&journal_path).unwrap();
+        assert_eq!(records.iter().map(|r| r.input_seq).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        let mut next_event_seq = 1;
+        for record in &records {
+            assert_eq!(record.first_event_seq, next_event_seq);
+            next_event_seq += record.events.len() as u64;
+        }
+        assert!(records[3].events.iter().any(|e| matches!(e, EngineEvent::TradeExecuted { .. })));
+
+        let wal = WalHandler::recover(&config.wal_dir, 0, &[]).unwrap();
+        assert_eq!(verify(&records, &wal.entries).unwrap(), 4);
+
+        // Crash sebelum record terakhir sampai ke disk: dibangun ulang dari WAL saat startup, hasilnya identik
+        let complete = std::fs::read(&journal_path).unwrap();
+        std::fs::write(&journal_path, &complete[..complete.len() - 5]).unwrap();
+        let (_tx, rx) = mpsc::channel(8);
+        drop(MarketProcessor::new(config.clone(), rx, broadcast_tx.clone()).unwrap());
+        assert_eq!(std::fs::read(&journal_path).unwrap(), complete);
+
+        // Journal yang tidak sesuai dengan hasil replay menggagalkan startup
+        std::fs::remove_file(&journal_path).unwrap();
+        let mut forged = EventJournal::open(&journal_path, &EventJournal::recover(&journal_path, 0, 0).unwrap()).unwrap();
+        forged.append(1, &wal.entries[0].1, &[]).unwrap();
+        forged.sync().unwrap();
+        let (_tx, rx) = mpsc::channel(8);
+        match MarketProcessor::new(config, rx, broadcast_tx) {
+            Err(WalError::JournalMismatch { input_seq, .. }) => assert_eq!(input_seq, 1),
+            other => panic!("Harusnya ditolak, dapat {:?}", other.err()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_journal_lost_after_snapshot_fails_startup() {
+        let dir = TempDir::new("journal_snapshot");
+        let config = market_config(&dir).with_snapshot_interval(0);
+        let journal_path = PathBuf::from(&config.journal_path);
+
+        let (tx, rx) = mpsc::channel(8);
+        let (broadcast_tx, _) = broadcast::channel(16);
+        let engine = tokio::spawn(MarketProcessor::new(config.clone(), rx, broadcast_tx.clone()).unwrap().run());
+
+        for user_id in 1..=2 {
+            let (command, reply) = deposit_command(user_id, user_id, Asset::Quote, 100);
+            tx.send(command).await.unwrap();
+            assert!(reply.await.unwrap().is_ok());
+        }
+        let (responder, snapshot) = oneshot::channel();
+        tx.send(Command::Snapshot { responder }).await.unwrap();
+        assert_eq!(snapshot.await.unwrap().unwrap(), 2);
+        let (command, reply) = deposit_command(3, 3, Asset::Quote, 100);
+        tx.send(command).await.unwrap();
+        assert!(reply.await.unwrap().is_ok());
+        drop(tx);
+        engine.await.unwrap();
+
+        // Event sebelum snapshot tidak bisa dibangun ulang: journal kosong tidak boleh mulai lagi dari event seq 1
+        let complete = fs::read(&journal_path).unwrap();
+        fs::remove_file(&journal_path).unwrap();
+        let (_tx, rx) = mpsc::channel(8);
+        match MarketProcessor::new(config.clone(), rx, broadcast_tx.clone()) {
+            Err(WalError::JournalMismatch { input_seq, .. }) => assert_eq!(input_seq, 1),
+            other => panic!("Harusnya ditolak, dapat {:?}", other.err()),
+        }
+
+        // Journal utuh: hanya record sesudah snapshot yang diverifikasi, posisi event-nya cocok dengan snapshot
+        fs::write(&journal_path, &complete).unwrap();
+        let (_tx, rx) = mpsc::channel(8);
+        assert!(MarketProcessor::new(config, rx, broadcast_tx).is_ok());
+        assert_eq!(fs::read(&journal_path).unwrap(), complete);
+    }
+}
@@ -0,0 +1,348 @@
+// crates/engine-core/src/ledger.rs
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use serde::{Serialize, Deserialize};
+use crate::{OrderBook, OrderId, UserId, Price, Quantity, Side, EngineEvent};
+use crate::fees::FeeSchedule;
+
+// Aset relatif terhadap satu market: Base (yang diperdagangkan) dan Quote (alat bayar), e.g. SOL / USDC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Asset {
+    Base,
+    Quote,
+}
+
+// Akun house: fee trade dikreditkan ke sini dan rebate maker dibayar dari sini (per aset quote),
+// jadi total saldo semua akun tetap sama dengan total deposit - withdrawal. User id ini dicadangkan
+pub const FEE_ACCOUNT: UserId = UserId::MAX;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balance {
+    // Bebas dipakai untuk order baru
+    pub available: u64,
+    // Terkunci oleh order yang masih hidup
+    pub locked: u64,
+}
+
+// Dana yang dikunci untuk satu order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reservation {
+    pub order_id: OrderId,
+    pub user_id: UserId,
+    pub asset: Asset,
+    pub amount: u64,
+}
+
+// Dana yang harus dikunci untuk order baru: Bid mengunci quote (price * quantity + fee maksimum),
+// Ask mengunci base (fee seller dipotong dari hasil penjualan). Jumlah yang overflow u64 dianggap tidak pernah cukup
+pub fn required_funds(side: Side, price: Price, quantity: Quantity, fees: &FeeSchedule) -> (Asset, u64) {
+    match side {
+        Side::Bid => {
+            let notional = (price as u128) * (quantity as u128);
+            (Asset::Quote, u64::try_from(fees.with_max_fee(notional)).unwrap_or(u64::MAX))
+        }
+        Side::Ask => (Asset::Base, quantity),
+    }
+}
+
+// Symbol aset base/quote satu market. Saldo disimpan per symbol aset, bukan per market,
+// jadi USDC yang didepositkan lewat SOL_USDC juga bisa dipakai di BTC_USDC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketAssets {
+    pub base: String,
+    pub quote: String,
+}
+
+impl MarketAssets {
+    pub fn new(base: &str, quote: &str) -> Self {
+        Self { base: base.to_string(), quote: quote.to_string() }
+    }
+
+    // Konvensi symbol market "<BASE>_<QUOTE>", e.g. SOL_USDC. Symbol lain mendapat aset sendiri
+    pub fn from_symbol(symbol: &str) -> Self {
+        match symbol.split_once('_') {
+            Some((base, quote)) if !base.is_empty() && !quote.is_empty() => Self::new(base, quote),
+            _ => Self::new(&format!("{}.BASE", symbol), &format!("{}.QUOTE", symbol)),
+        }
+    }
+
+    pub fn symbol(&self, asset: Asset) -> &str {
+        match asset {
+            Asset::Base => &self.base,
+            Asset::Quote => &self.quote,
+        }
+    }
+}
+
+impl Default for MarketAssets {
+    fn default() -> Self {
+        Self::new("BASE", "QUOTE")
+    }
+}
+
+// Perubahan saldo bertanda. Kontribusi satu market bisa negatif, e.g. market ini
+// membelanjakan USDC yang didepositkan lewat market lain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Delta {
+    available: i128,
+    locked: i128,
+}
+
+impl Delta {
+    fn add(&mut self, other: Delta) {
+        self.available += other.available;
+        self.locked += other.locked;
+    }
+
+    // Total semua market tidak pernah negatif, kecuali sesaat selama recovery
+    fn balance(&self) -> Balance {
+        Balance {
+            available: u64::try_from(self.available).unwrap_or(0),
+            locked: u64::try_from(self.locked).unwrap_or(0),
+        }
+    }
+}
+
+// Saldo semua user lintas market, key = (user, symbol aset). Selalu sama dengan jumlah kontribusi
+// Ledger setiap market yang ter-attach, jadi tidak perlu disimpan sendiri: recovery setiap market
+// membangun ulang kontribusinya dari snapshot + WAL-nya sendiri
+#[derive(Debug, Default)]
+struct Accounts {
+    balances: HashMap<(UserId, String), Delta>,
+    // Journal id deposit/withdrawal dari semua market
+    journal_ids: HashSet<u64>,
+}
+
+// Handle Accounts yang dipakai bersama oleh semua market di satu registry.
+// Dengan GroupCommit/FlushOnly, saldo dari entry yang belum durable sudah bisa dipakai market lain:
+// crash di jendela tersebut bisa meninggalkan belanja di satu WAL tanpa dana asalnya di WAL lain
+#[derive(Debug, Clone, Default)]
+pub struct SharedAccounts {
+    accounts: Arc<Mutex<Accounts>>,
+    // Dipegang processor dari cek saldo sampai entry-nya diterapkan (termasuk tulis WAL),
+    // agar dua market tidak bisa memakai saldo yang sama secara bersamaan
+    admission: Arc<Mutex<()>>,
+}
+
+impl SharedAccounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn admit(&self) -> MutexGuard<'_, ()> {
+        self.admission.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Accounts> {
+        self.accounts.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Saldo per user per aset sebagaimana dilihat dari satu market. Hanya diubah lewat entry WAL
+// dan event hasil eksekusinya. Ledger menyimpan kontribusi market ini (yang masuk snapshot),
+// setiap perubahan ikut diterapkan ke SharedAccounts, sedangkan pembacaan saldo memakai total lintas market.
+// Cek saldo dilakukan processor sebelum WAL, jadi perubahan di sini tidak pernah ditolak
+// dan replay menghasilkan kontribusi yang identik walaupun market lain belum di-recover
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    balances: HashMap<(UserId, Asset), Delta>,
+    // Order ID -> sisa dana yang masih terkunci untuk order tersebut
+    reservations: HashMap<OrderId, Reservation>,
+    // Journal id deposit/withdrawal (dari settlement layer) yang sudah diterapkan di market ini
+    journal_ids: HashSet<u64>,
+    // Dari konfigurasi market, bukan dari snapshot
+    #[serde(skip)]
+    assets: MarketAssets,
+    #[serde(skip)]
+    accounts: SharedAccounts,
+}
+
+impl Ledger {
+    // Ledger berdiri sendiri (Accounts tidak dibagi dengan market lain)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Pindahkan kontribusi market ini ke `accounts` dan pakai Accounts tersebut mulai sekarang
+    pub fn attach(&mut self, assets: MarketAssets, accounts: SharedAccounts) {
+        {
+            let mut shared = accounts.lock();
+            for (&(user_id, asset), delta) in &self.balances {
+                shared.balances.entry((user_id, assets.symbol(asset).to_string())).or_default().add(*delta);
+            }
+            shared.journal_ids.extend(&self.journal_ids);
+        }
+        self.assets = assets;
+        self.accounts = accounts;
+    }
+
+    pub fn accounts(&self) -> &SharedAccounts {
+        &self.accounts
+    }
+
+    pub fn assets(&self) -> &MarketAssets {
+        &self.assets
+    }
+
+    // Saldo total user di semua market untuk aset ini
+    pub fn balance(&self, user_id: UserId, asset: Asset) -> Balance {
+        let key = (user_id, self.assets.symbol(asset).to_string());
+        self.accounts.lock().balances.get(&key).map(Delta::balance).unwrap_or_default()
+    }
+
+    pub fn available(&self, user_id: UserId, asset: Asset) -> u64 {
+        self.balance(user_id, asset).available
+    }
+
+    pub fn reserved(&self, order_id: OrderId) -> u64 {
+        self.reservations.get(&order_id).map_or(0, |r| r.amount)
+    }
+
+    // Apakah mutasi dengan journal_id ini sudah diterapkan (di market mana pun)
+    pub fn is_journaled(&self, journal_id: u64) -> bool {
+        self.accounts.lock().journal_ids.contains(&journal_id)
+    }
+
+    // Deposit eksternal. False jika journal_id sudah pernah diterapkan di market ini (replay)
+    pub fn deposit(&mut self, journal_id: u64, user_id: UserId, asset: Asset, amount: u64) -> bool {
+        if !self.journal(journal_id) {
+            return false;
+        }
+        self.credit(user_id, asset, amount);
+        true
+    }
+
+    // Withdrawal dari saldo available (processor sudah memastikan saldo cukup, dana terkunci tidak tersentuh).
+    // False jika journal_id sudah pernah diterapkan di market ini
+    pub fn withdraw(&mut self, journal_id: u64, user_id: UserId, asset: Asset, amount: u64) -> bool {
+        if !self.journal(journal_id) {
+            return false;
+        }
+        self.adjust(user_id, asset, -(amount as i128), 0);
+        true
+    }
+
+    // Catat journal id, false jika sudah pernah diterapkan
+    fn journal(&mut self, journal_id: u64) -> bool {
+        if !self.journal_ids.insert(journal_id) {
+            return false;
+        }
+        self.accounts.lock().journal_ids.insert(journal_id);
+        true
+    }
+
+    // Tambah saldo available
+    pub fn credit(&mut self, user_id: UserId, asset: Asset, amount: u64) {
+        self.adjust(user_id, asset, amount as i128, 0);
+    }
+
+    // Satu-satunya jalur perubahan saldo: kontribusi market ini dan total di Accounts berubah bersama
+    fn adjust(&mut self, user_id: UserId, asset: Asset, available: i128, locked: i128) {
+        let delta = Delta { available, locked };
+        self.balances.entry((user_id, asset)).or_default().add(delta);
+        let key = (user_id, self.assets.symbol(asset).to_string());
+        self.accounts.lock().balances.entry(key).or_default().add(delta);
+    }
+
+    // Apakah reservasi order bisa diubah menjadi `reservation.amount`
+    // (order baru: reservasi lama = 0, amend: selisihnya saja yang perlu available)
+    pub fn can_reserve(&self, reservation: &Reservation) -> bool {
+        let current = self.reserved(reservation.order_id);
+        reservation.amount <= current || reservation.amount - current <= self.available(reservation.user_id, reservation.asset)
+    }
+
+    // Set dana terkunci untuk order menjadi `reservation.amount` (cek dengan can_reserve lebih dulu).
+    // Kekurangan diambil dari available, kelebihan dikembalikan
+    pub fn reserve(&mut self, reservation: Reservation) {
+        let change = reservation.amount as i128 - self.reserved(reservation.order_id) as i128;
+        self.adjust(reservation.user_id, reservation.asset, -change, change);
+        self.reservations.insert(reservation.order_id, reservation);
+    }
+
+    // Proses event hasil eksekusi: settle setiap trade, lalu lepas sisa dana order yang sudah selesai
+    // (terisi penuh, cancel, expired, unfilled, ditolak). Order yang masih hidup tapi dikurangi STP
+    // Decrement-And-Cancel hanya menyimpan dana untuk sisa quantity-nya
+    pub fn apply_events(&mut self, events: &[EngineEvent], book: &OrderBook, fees: &FeeSchedule) {
+        for event in events {
+            if let EngineEvent::TradeExecuted { maker_id, taker_id, price, quantity, maker_fee, taker_fee, .. } = *event {
+                self.settle_fill(maker_id, price, quantity, maker_fee);
+                self.settle_fill(taker_id, price, quantity, taker_fee);
+            }
+        }
+
+        for event in events {
+            for order_id in touched_orders(event).into_iter().flatten() {
+                if !book.is_live(order_id) {
+                    self.release(order_id);
+                } else if let (EngineEvent::SelfTradeDecremented { .. }, Some(order)) = (event, book.order(order_id)) {
+                    let (_, needed) = required_funds(order.side, order.price, order.total_quantity(), fees);
+                    self.trim(order_id, needed);
+                }
+            }
+        }
+    }
+
+    // Satu sisi trade: dana terkunci dibayarkan, aset lawan masuk ke available.
+    // Fee (quote) dibayar buyer dari dana terkunci, seller dari hasil penjualan; rebate masuk ke available quote
+    fn settle_fill(&mut self, order_id: OrderId, price: Price, quantity: Quantity, fee: i64) {
+        let Some(reservation) = self.reservations.get_mut(&order_id) else {
+            return;
+        };
+
+        // Dihitung di u128 seperti required_funds, fill sebesar apa pun tidak bisa overflow
+        let notional = (price as u128) * (quantity as u128);
+        let charged = fee.max(0) as u128;
+        let rebate = fee.min(0).unsigned_abs() as u128;
+
+        // Seller membayar fee dari hasil penjualan (tidak pernah lebih dari hasilnya)
+        let (paid, received, received_asset, charged) = match reservation.asset {
+            Asset::Quote => (notional + charged, quantity as u128, Asset::Base, charged),
+            Asset::Base => (quantity as u128, notional.saturating_sub(charged), Asset::Quote, charged.min(notional)),
+        };
+        let paid = u64::try_from(paid).unwrap_or(u64::MAX);
+
+        reservation.amount = reservation.amount.saturating_sub(paid);
+        let (user_id, asset) = (reservation.user_id, reservation.asset);
+
+        self.adjust(user_id, asset, 0, -(paid as i128));
+        self.adjust(user_id, received_asset, received as i128, 0);
+        if fee != 0 {
+            self.adjust(FEE_ACCOUNT, Asset::Quote, charged as i128 - rebate as i128, 0);
+            self.adjust(user_id, Asset::Quote, rebate as i128, 0);
+        }
+    }
+
+    // Kecilkan dana terkunci order menjadi paling banyak `amount`, kelebihannya kembali ke available
+    fn trim(&mut self, order_id: OrderId, amount: u64) {
+        if let Some(&reservation) = self.reservations.get(&order_id).filter(|r| r.amount > amount) {
+            self.reserve(Reservation { amount, ..reservation });
+        }
+    }
+
+    // Kembalikan sisa dana terkunci order ke available
+    fn release(&mut self, order_id: OrderId) {
+        let Some(reservation) = self.reservations.remove(&order_id) else {
+            return;
+        };
+
+        let amount = reservation.amount as i128;
+        self.adjust(reservation.user_id, reservation.asset, amount, -amount);
+    }
+}
+
+// Order yang statusnya mungkin berubah karena event ini
+pub(crate) fn touched_orders(event: &EngineEvent) -> [Option<OrderId>; 2] {
+    match *event {
+        EngineEvent::TradeExecuted { maker_id, taker_id, .. }
+        | EngineEvent::SelfTradeDecremented { maker_id, taker_id, .. } => [Some(maker_id), Some(taker_id)],
+        EngineEvent::OrderCancelled { id, .. }
+        | EngineEvent::StopCancelled { id, .. }
+        | EngineEvent::OrderUnfilled { id, .. }
+        | EngineEvent::OrderKilled { id, .. }
+        | EngineEvent::OrderExpired { id, .. }
+        | EngineEvent::OrderAmended { id, .. }
+        | EngineEvent::PostOnlyRejected { id, .. } => [Some(id), None],
+        _ => [None, None],
+    }
+}
@@ -1,13 +1,32 @@
 // crates/engine-core/src/lib.rs
 
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use serde::{Serialize, Deserialize};
 use slab::Slab;
 
+pub mod clock;
+pub mod error;
+pub mod fees;
+pub mod instrument;
+pub mod journal;
+pub mod ledger;
+pub mod orders;
 pub mod processor;
+pub mod registry;
+pub mod snapshot;
+pub mod stops;
 pub mod wal;
+#[cfg(test)]
+mod test_support;
+
+use ledger::Asset;
+use stops::{StopOrder, TriggerBook};
 
 // --- Data Structures (Optimize for Cache Locality & Copy) ---
+// Versi logika matching. Naikkan setiap perubahan yang membuat replay entry WAL yang sama menghasilkan
+// state berbeda, agar log lama tidak di-replay diam-diam dengan logika baru (lihat wal::Migration)
+pub const MATCHING_VERSION: u32 = 1;
+
 pub type OrderId = u64;
 pub type UserId = u64;
 pub type Price = u64; // Menggunakan atomic units (misal: satoshi) untuk menghindari Floating Point errors
@@ -28,6 +47,94 @@ impl Side {
     }
 }
 
+// Berapa lama order boleh hidup di buku
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TimeInForce {
+    // Good-Til-Cancelled: sisa order masuk buku (default)
+    #[default]
+    Gtc,
+    // Immediate-Or-Cancel: isi sebanyak mungkin, sisa dibuang
+    Ioc,
+    // Fill-Or-Kill: harus terisi penuh, kalau tidak seluruh order dibatalkan tanpa trade
+    Fok,
+    // Good-Til-Date: seperti GTC, tapi kedaluwarsa pada waktu engine `expires_at` (ms)
+    Gtd { expires_at: u64 },
+}
+
+impl TimeInForce {
+    // Apakah sisa order boleh masuk buku setelah fase taker
+    pub fn rests(&self) -> bool {
+        matches!(self, TimeInForce::Gtc | TimeInForce::Gtd { .. })
+    }
+
+    pub fn expires_at(&self) -> Option<u64> {
+        match *self {
+            TimeInForce::Gtd { expires_at } => Some(expires_at),
+            _ => None,
+        }
+    }
+}
+
+// Post-Only (Maker-Only): order tidak boleh menjadi taker di fase matching
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PostOnly {
+    #[default]
+    Disabled,
+    // Tolak order jika akan crossing spread
+    Reject,
+    // Geser harga ke harga terbaik yang tidak crossing (1 tick di belakang best lawan)
+    Slide,
+}
+
+// Self-Trade Prevention: apa yang terjadi jika taker bertemu order milik owner yang sama
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SelfTradePrevention {
+    // Resting order (maker) dibatalkan, taker lanjut matching
+    #[default]
+    CancelMaker,
+    // Sisa taker dibatalkan, maker tetap di buku
+    CancelTaker,
+    // Maker dan sisa taker sama-sama dibatalkan
+    CancelBoth,
+    // Kedua order dikurangi sebesar quantity yang lebih kecil, yang habis dibatalkan
+    DecrementAndCancel,
+}
+
+// Policy STP per order. Order dengan `group` yang sama dianggap satu owner
+// (misal beberapa sub-account milik satu entitas)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct StpPolicy {
+    pub mode: SelfTradePrevention,
+    pub group: Option<u64>,
+}
+
+// Parameter tambahan untuk order baru. Default = Limit GTC biasa
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OrderOptions {
+    pub time_in_force: TimeInForce,
+    pub post_only: PostOnly,
+    pub stp: StpPolicy,
+    // Iceberg: hanya quantity ini yang terlihat di buku, sisanya jadi reserve tersembunyi
+    pub display_quantity: Option<Quantity>,
+}
+
+// Filter mass cancel. Semua None = semua order milik user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancelFilter {
+    pub side: Option<Side>,
+    // Rentang harga inklusif (stop order memakai limit price, atau trigger price untuk Stop-Market)
+    pub min_price: Option<Price>,
+    pub max_price: Option<Price>,
+}
+
+impl CancelFilter {
+    pub fn matches(&self, side: Side, price: Price) -> bool {
+        self.side.is_none_or(|s| s == side)
+            && self.min_price.is_none_or(|min| price >= min)
+            && self.max_price.is_none_or(|max| price <= max)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Order {
     pub id: OrderId,
@@ -36,9 +143,136 @@ pub struct Order {
     pub quantity: Quantity,
     pub side: Side,
     pub timestamp: u64,
+    // GTD: waktu engine (ms) saat order kedaluwarsa
+    pub expires_at: Option<u64>,
+    pub stp: StpPolicy,
+    // Iceberg: ukuran slice yang terlihat (0 = bukan iceberg) dan reserve tersembunyi
+    pub display_quantity: Quantity,
+    pub hidden_quantity: Quantity,
 }
 
-#[derive(Debug, Clone)]
+impl Order {
+    // Sisa quantity total (terlihat + tersembunyi)
+    pub fn total_quantity(&self) -> Quantity {
+        self.quantity + self.hidden_quantity
+    }
+
+    // Event untuk order ini saat dikeluarkan dari buku dengan sisa quantity-nya
+    pub fn cancelled(&self, reason: CancelReason) -> EngineEvent {
+        EngineEvent::OrderCancelled {
+            id: self.id,
+            user_id: self.user_id,
+            side: self.side,
+            price: Some(self.price),
+            quantity: self.total_quantity(),
+            displayed_quantity: self.quantity,
+            reason,
+        }
+    }
+
+    // Event untuk GTD order ini saat dikeluarkan dari buku karena melewati expires_at
+    pub fn expired(&self) -> EngineEvent {
+        EngineEvent::OrderExpired {
+            id: self.id,
+            user_id: self.user_id,
+            side: self.side,
+            price: self.price,
+            quantity: self.total_quantity(),
+            displayed_quantity: self.quantity,
+        }
+    }
+
+    // Iceberg: isi ulang slice yang terlihat dari reserve tersembunyi.
+    // Mengembalikan false jika reserve sudah habis
+    fn replenish(&mut self) -> bool {
+        if self.hidden_quantity == 0 {
+            return false;
+        }
+        let slice = std::cmp::min(self.display_quantity, self.hidden_quantity);
+        self.quantity = slice;
+        self.hidden_quantity -= slice;
+        true
+    }
+}
+
+// Order yang sedang masuk (taker) di fase matching
+struct Taker {
+    id: OrderId,
+    user_id: UserId,
+    side: Side,
+    stp: StpPolicy,
+    // Market buy: batas total quote yang boleh dibelanjakan (dana yang dikunci ledger)
+    budget: Option<u64>,
+    // Iceberg: ukuran slice yang terlihat (0 = bukan iceberg)
+    display_quantity: Quantity,
+}
+
+impl Taker {
+    // Satu owner jika user sama, atau keduanya berada di STP group yang sama
+    fn is_same_owner(&self, maker: &Order) -> bool {
+        maker.user_id == self.user_id
+            || (self.stp.group.is_some() && maker.stp.group == self.stp.group)
+    }
+
+    // Bagian dari sisa quantity yang akan terlihat di buku
+    fn displayed(&self, quantity: Quantity) -> Quantity {
+        match self.display_quantity {
+            0 => quantity,
+            display => std::cmp::min(display, quantity),
+        }
+    }
+}
+
+// Alasan request order ditolak (validasi, dana, identitas order, kepemilikan)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+pub enum RejectReason {
+    #[error("price must be non-zero")]
+    InvalidPrice,
+    #[error("price is not a multiple of the tick size")]
+    PriceNotOnTick,
+    #[error("quantity must be non-zero")]
+    InvalidQuantity,
+    #[error("quantity is not a multiple of the lot size")]
+    QuantityNotOnLot,
+    #[error("quantity is below the instrument minimum")]
+    QuantityBelowMinimum,
+    #[error("quantity is above the instrument maximum")]
+    QuantityAboveMaximum,
+    // price * quantity di bawah min notional instrument
+    #[error("notional is below the instrument minimum")]
+    NotionalBelowMinimum,
+    // Saldo available tidak cukup untuk dikunci
+    #[error("insufficient available balance")]
+    InsufficientBalance,
+    // Order ID sudah dipakai request lain (atau masih hidup di buku)
+    #[error("order id is already in use")]
+    DuplicateOrderId,
+    // Client order ID masih dipakai order hidup milik user yang sama
+    #[error("client order id is already in use")]
+    DuplicateClientOrderId,
+    // Cancel/amend untuk order yang tidak ada (atau sudah selesai)
+    #[error("order not found")]
+    OrderNotFound,
+    // Cancel/amend order milik user lain
+    #[error("order belongs to another user")]
+    NotOrderOwner,
+    // Market berhenti menerima perubahan setelah WAL gagal ditulis
+    #[error("market is halted")]
+    MarketHalted,
+}
+
+// Penyebab order keluar dari buku sebelum terisi penuh
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CancelReason {
+    // Cancel oleh pemilik order
+    UserRequested,
+    // Dibuang oleh Self-Trade Prevention
+    SelfTradePrevention,
+    // Cancel oleh operator / risk system
+    Admin,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EngineEvent {
     OrderPlaced {
         id: OrderId, 
@@ -47,15 +281,148 @@ pub enum EngineEvent {
         quantity: Quantity, 
         side: Side
     },
+    // Order dikeluarkan dari buku (atau sisa taker dibuang), quantity = sisa yang tidak terisi
+    // termasuk reserve iceberg. price None = taker market order
     OrderCancelled {
-        id: OrderId
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        price: Option<Price>,
+        quantity: Quantity,
+        // Bagian sisa yang terlihat di buku (tanpa reserve iceberg), untuk market data publik
+        displayed_quantity: Quantity,
+        reason: CancelReason
     },
     TradeExecuted {
         maker_id: OrderId, 
         taker_id: OrderId, 
+        maker_user_id: UserId,
+        taker_user_id: UserId,
         price: Price, 
+        quantity: Quantity,
+        // Fee dalam quote asset (negatif = rebate). Diisi oleh FeeEngine di MarketProcessor,
+        // OrderBook sendiri selalu mengisi 0
+        maker_fee: i64,
+        taker_fee: i64
+    },
+    // Sisa market/IOC order yang tidak terisi (expired unfilled, tidak masuk buku)
+    OrderUnfilled {
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        quantity: Quantity
+    },
+    // FOK order yang tidak bisa terisi penuh, dibatalkan sebelum ada trade
+    OrderKilled {
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        quantity: Quantity
+    },
+    // GTD order yang melewati expires_at, dikeluarkan dari buku (atau langsung kedaluwarsa saat masuk).
+    // quantity = sisa termasuk reserve iceberg, displayed_quantity = bagian yang terlihat di buku
+    OrderExpired {
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        displayed_quantity: Quantity
+    },
+    // Slice iceberg yang terisi habis diisi ulang dari reserve dan pindah ke belakang antrian
+    OrderReplenished {
+        id: OrderId,
+        side: Side,
+        price: Price,
+        quantity: Quantity
+    },
+    // Order resting yang diubah harga/quantity-nya (id tetap sama).
+    // quantity = sisa yang terlihat di buku setelah amend (0 jika habis ter-match)
+    OrderAmended {
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        priority_kept: bool
+    },
+    // Stop order disimpan di Trigger Store (belum terlihat di buku)
+    StopPlaced {
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        trigger_price: Price,
+        limit_price: Option<Price>,
         quantity: Quantity
     },
+    // Harga trade menyentuh trigger, stop diaktifkan menjadi market/limit order
+    // (diikuti event eksekusi order tersebut)
+    StopTriggered {
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        trigger_price: Price
+    },
+    StopCancelled {
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        quantity: Quantity,
+        reason: CancelReason
+    },
+    // Ringkasan mass cancel, setelah satu OrderCancelled/StopCancelled per order
+    MassCancelled {
+        user_id: UserId,
+        count: u64
+    },
+    // Decrement-And-Cancel STP: kedua order dikurangi tanpa trade
+    SelfTradeDecremented {
+        maker_id: OrderId,
+        taker_id: OrderId,
+        quantity: Quantity
+    },
+    // Post-Only order yang akan crossing spread, ditolak tanpa trade
+    PostOnlyRejected {
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        price: Price
+    },
+    // Post-Only order yang harganya digeser agar tidak crossing (diikuti OrderPlaced)
+    PostOnlySlid {
+        id: OrderId,
+        user_id: UserId,
+        side: Side,
+        original_price: Price,
+        price: Price
+    },
+    // Request order (place/cancel/amend) yang ditolak, buku tidak berubah
+    OrderRejected {
+        id: OrderId,
+        user_id: UserId,
+        reason: RejectReason
+    },
+    // Saldo available bertambah dari deposit eksternal
+    FundsDeposited {
+        journal_id: u64,
+        user_id: UserId,
+        asset: Asset,
+        amount: u64
+    },
+    // Saldo available berkurang untuk withdrawal (dana terkunci tidak pernah tersentuh)
+    FundsWithdrawn {
+        journal_id: u64,
+        user_id: UserId,
+        asset: Asset,
+        amount: u64
+    },
+    // Withdrawal melebihi saldo available, tidak ditulis ke WAL
+    WithdrawalRejected {
+        user_id: UserId,
+        asset: Asset,
+        amount: u64,
+        available: u64
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -64,7 +431,7 @@ pub struct OrderLevel {
     pub quantity: Quantity,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LogEntry {
     Place {
         order_id: OrderId,
@@ -72,14 +439,131 @@ pub enum LogEntry {
         side: Side,
         price: Price,
         quantity: Quantity,
+        time_in_force: TimeInForce,
+        post_only: PostOnly,
+        stp: StpPolicy,
+        display_quantity: Option<Quantity>,
+        client_order_id: Option<String>,
+        timestamp: u64,
     },
     Cancel {
         order_id: OrderId,
         user_id: UserId,
+    },
+    // Mass cancel: satu entry untuk semua order user yang cocok dengan filter
+    CancelAll {
+        user_id: UserId,
+        filter: CancelFilter,
+    },
+    PlaceMarket {
+        order_id: OrderId,
+        user_id: UserId,
+        side: Side,
+        quantity: Quantity,
+        stp: StpPolicy,
+        client_order_id: Option<String>,
+        // Market buy: quote yang dikunci sebagai budget saat order diterima (biaya sweep, dibatasi saldo).
+        // Dicatat di entry karena saldo bisa berasal dari market lain yang tidak ikut di-replay
+        locked_quote: Option<u64>,
+        timestamp: u64,
+    },
+    Amend {
+        order_id: OrderId,
+        user_id: UserId,
+        price: Price,
+        quantity: Quantity,
+        timestamp: u64,
+    },
+    PlaceStop {
+        order_id: OrderId,
+        user_id: UserId,
+        side: Side,
+        trigger_price: Price,
+        limit_price: Option<Price>,
+        quantity: Quantity,
+        stp: StpPolicy,
+        client_order_id: Option<String>,
+        timestamp: u64,
+    },
+    // Penanda audit: stop ini ter-trigger oleh entry sebelumnya.
+    // Saat replay dipakai untuk verifikasi bahwa trigger terjadi di titik yang sama
+    StopTriggered {
+        order_id: OrderId,
+    },
+    // Sapu GTD order yang kedaluwarsa pada waktu engine `timestamp`
+    Expire {
+        timestamp: u64,
+    },
+    // Mutasi saldo dari luar engine. journal_id diberikan settlement layer dan unik per mutasi,
+    // entry dengan journal_id yang sudah diterapkan diabaikan (aman di-retry dan di-replay ulang)
+    Deposit {
+        journal_id: u64,
+        user_id: UserId,
+        asset: Asset,
+        amount: u64,
+        timestamp: u64,
+    },
+    Withdraw {
+        journal_id: u64,
+        user_id: UserId,
+        asset: Asset,
+        amount: u64,
+        timestamp: u64,
+    },
+}
+
+// Identitas order baru pada entry placement. price None = market (atau Stop-Market)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement<'a> {
+    pub order_id: OrderId,
+    pub user_id: UserId,
+    pub side: Side,
+    pub price: Option<Price>,
+    pub client_order_id: Option<&'a str>,
+}
+
+impl LogEntry {
+    // Waktu engine yang tercatat di entry ini (sumber waktu satu-satunya saat replay)
+    pub fn timestamp(&self) -> Option<u64> {
+        match *self {
+            LogEntry::Place { timestamp, .. }
+            | LogEntry::PlaceMarket { timestamp, .. }
+            | LogEntry::Amend { timestamp, .. }
+            | LogEntry::PlaceStop { timestamp, .. }
+            | LogEntry::Expire { timestamp }
+            | LogEntry::Deposit { timestamp, .. }
+            | LogEntry::Withdraw { timestamp, .. } => Some(timestamp),
+            LogEntry::Cancel { .. } | LogEntry::CancelAll { .. } | LogEntry::StopTriggered { .. } => None,
+        }
+    }
+
+    // Order baru yang dibuat entry ini (hanya entry placement)
+    pub fn placement(&self) -> Option<Placement<'_>> {
+        let (order_id, user_id, side, price, client_order_id) = match self {
+            LogEntry::Place { order_id, user_id, side, price, client_order_id, .. } => {
+                (order_id, user_id, side, Some(*price), client_order_id)
+            }
+            LogEntry::PlaceMarket { order_id, user_id, side, client_order_id, .. } => {
+                (order_id, user_id, side, None, client_order_id)
+            }
+            LogEntry::PlaceStop { order_id, user_id, side, limit_price, client_order_id, .. } => {
+                (order_id, user_id, side, *limit_price, client_order_id)
+            }
+            _ => return None,
+        };
+
+        Some(Placement {
+            order_id: *order_id,
+            user_id: *user_id,
+            side: *side,
+            price,
+            client_order_id: client_order_id.as_deref(),
+        })
     }
 }
 
 // --- The Matching Engine (Core Logic) --- 
+#[derive(Serialize, Deserialize)]
 pub struct OrderBook {
     // Penyimpanan data order sebenarnya. Menggunakan Slab untuk akses O(1) dan reuse memory slot
     // Ini lebih efisien daripada Box::new() setiap kali order baru masuk
@@ -89,22 +573,92 @@ pub struct OrderBook {
     bids: BTreeMap<Price, VecDeque<usize>>, 
     asks: BTreeMap<Price, VecDeque<usize>>, 
     order_index: HashMap<OrderId, usize>,
+
+    // Stop / Stop-Limit order yang menunggu trigger (terpisah dari bids/asks)
+    stops: TriggerBook,
+    last_trade_price: Option<Price>,
+    // Rentang harga trade (low, high) sejak aktivasi stop terakhir
+    trade_range: Option<(Price, Price)>,
+
+    // Waktu engine (ms), hanya maju lewat advance_time() dari timestamp di WAL
+    now: u64,
+    // Indeks GTD: (expires_at, order id), urut dari yang paling cepat kedaluwarsa
+    expiries: BTreeSet<(u64, OrderId)>,
+    // Jarak harga minimum, dipakai saat Post-Only slide
+    tick_size: Price,
     #[allow(dead_code)] 
     sequence: u64, 
 }
 
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OrderBook {
     pub fn new() -> Self {
+        Self::with_tick_size(1)
+    }
+
+    pub fn with_tick_size(tick_size: Price) -> Self {
         Self {
             order_store: Slab::with_capacity(10_000), // Pre-allocate memory
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
             order_index: HashMap::new(),
+            stops: TriggerBook::new(),
+            last_trade_price: None,
+            trade_range: None,
+            now: 0,
+            expiries: BTreeSet::new(),
+            tick_size,
             sequence: 0,
         }
     }
 
-    // Fungsi utama untuk memproses Limit Order
+    // Majukan waktu engine. Tidak pernah mundur, agar urutan timestamp order tetap monoton
+    pub fn advance_time(&mut self, timestamp: u64) {
+        self.now = self.now.max(timestamp);
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    // Waktu kedaluwarsa GTD terdekat (dipakai processor untuk menjadwalkan sweep)
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.expiries.iter().next().map(|&(expires_at, _)| expires_at)
+    }
+
+    // Keluarkan semua GTD order yang expires_at <= now
+    pub fn expire_orders(&mut self, now: u64) -> Vec<EngineEvent> {
+        self.advance_time(now);
+
+        let mut events = Vec::new();
+
+        while let Some(&(expires_at, order_id)) = self.expiries.iter().next() {
+            if expires_at > self.now {
+                break;
+            }
+            self.expiries.remove(&(expires_at, order_id));
+
+            // Entry basi (order sudah terisi/cancel) dilewati saja
+            let Some(&internal_idx) = self.order_index.get(&order_id) else {
+                continue;
+            };
+            if self.order_store[internal_idx].expires_at != Some(expires_at) {
+                continue;
+            }
+
+            let order = self.unlink_order(internal_idx);
+            events.push(order.expired());
+        }
+
+        events
+    }
+
+    // Fungsi utama untuk memproses Limit Order (GTC)
     // Mengembalikan daftar event yang terjadi (Trade, Placement, dll)
     pub fn place_limit_order(
         &mut self,
@@ -112,135 +666,733 @@ impl OrderBook {
         user_id: UserId,
         side: Side,
         price: Price,
-        mut quantity: Quantity
+        quantity: Quantity
     ) -> Vec<EngineEvent> {
-        let mut events = Vec::new();
+        self.place_order(order_id, user_id, side, price, quantity, OrderOptions::default())
+    }
 
-        // 1. Matching Process (Taker Phase)
-        // Mencoba mencocokkan order yang masuk dengan order yang ada di buku
-        loop {
-            if quantity == 0 {
-                break;
-            }
+    // Limit Order dengan opsi tambahan (Time-In-Force, dll)
+    pub fn place_order(
+        &mut self,
+        order_id: OrderId,
+        user_id: UserId,
+        side: Side,
+        price: Price,
+        quantity: Quantity,
+        options: OrderOptions
+    ) -> Vec<EngineEvent> {
+        let mut events = self.execute_order(order_id, user_id, side, price, quantity, options);
+        self.activate_stops(&mut events);
+        events
+    }
 
-            // Cari order lawan terbaik (Best Bid or Best Ask)
-            let best_match_option = match side {
-                Side::Bid => self.asks.iter_mut().next(),
-                Side::Ask => self.bids.iter_mut().next_back(), 
-            };
+    fn execute_order(
+        &mut self,
+        order_id: OrderId,
+        user_id: UserId,
+        side: Side,
+        mut price: Price,
+        quantity: Quantity,
+        options: OrderOptions
+    ) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+        let taker = Taker {
+            id: order_id, user_id, side, stp: options.stp, budget: None, display_quantity: options.display_quantity.unwrap_or(0)
+        };
 
-            // Jika tidak ada liquidity, stop matching
-            let (best_price, order_queue) = match best_match_option {
-                Some((p, q)) => (*p, q),
-                None => break,
-            };
+        // 0. GTD yang sudah lewat waktunya tidak boleh trade sama sekali
+        if options.time_in_force.expires_at().is_some_and(|expires_at| expires_at <= self.now) {
+            events.push(EngineEvent::OrderExpired {
+                id: order_id, user_id, side, price, quantity, displayed_quantity: taker.displayed(quantity)
+            });
+            return events;
+        }
 
-            // Cek apakah harga memenuhi syarat
-            // Bid: beli jika harga lawan <= harga limit saya
-            // Ask: jual jika harga lawan >= harga limit saya
-            let is_matchable = match side {
-                Side::Bid => best_price <= price,
-                Side::Ask => best_price >= price,
-            };
+        // 0. Post-Only: order tidak boleh mengambil liquidity di fase taker
+        if options.post_only != PostOnly::Disabled {
+            if let Some(best_opposite) = self.crossing_price(side, price) {
+                // Harga terbaik yang tidak crossing: 1 tick di belakang best lawan
+                let slid_price = match side {
+                    Side::Bid => best_opposite.checked_sub(self.tick_size).filter(|&p| p > 0),
+                    Side::Ask => best_opposite.checked_add(self.tick_size),
+                };
 
-            if !is_matchable {
-                break;
+                match (options.post_only, slid_price) {
+                    (PostOnly::Slide, Some(slid_price)) => {
+                        events.push(EngineEvent::PostOnlySlid {
+                            id: order_id,
+                            user_id,
+                            side,
+                            original_price: price,
+                            price: slid_price,
+                        });
+                        price = slid_price;
+                    }
+                    _ => {
+                        events.push(EngineEvent::PostOnlyRejected { id: order_id, user_id, side, price });
+                        return events;
+                    }
+                }
             }
+        }
 
-            // Proses queue pada harga terbaik
-            while let Some(&maker_idx) = order_queue.front() {
-                // Ambil referensi mutable ke maker order
-                let maker_order = self.order_store.get_mut(maker_idx).expect("Stale index in queue");
 
-                // Self-Trade Prevention 
-                if maker_order.user_id == user_id {
-                    // Cancel Maker (Resting Order dibuang)
-                    // Agar loop tidak macet, sebaiknya harus pop order ini.
-                    order_queue.pop_front();
-                    
-                    events.push(EngineEvent::OrderCancelled { id: maker_order.id });
-                    
-                    // Hapus dari Slab
-                    self.order_store.remove(maker_idx);
-                    
-                    // Lanjut ke order berikutnya di antrian yang sama
-                    continue; 
-                }
+        // 0. FOK: pastikan seluruh quantity bisa terisi sebelum ada trade yang di-emit
+        if options.time_in_force == TimeInForce::Fok
+            && self.matchable_quantity(&taker, price, quantity) < quantity
+        {
+            events.push(EngineEvent::OrderKilled { id: order_id, user_id, side, quantity });
+            return events;
+        }
 
-                // Hitung jumlah yang bisa di-trade
-                let trade_qty = std::cmp::min(quantity, maker_order.quantity);
+        // 1. Matching Process (Taker Phase)
+        let quantity = self.match_incoming(&taker, Some(price), quantity, &mut events);
 
-                // Emit Trade Event
-                events.push(EngineEvent::TradeExecuted {
-                    maker_id: maker_order.id, 
-                    taker_id: order_id, 
-                    price: best_price,
-                    quantity: trade_qty,
-                });
+        if quantity == 0 {
+            return events;
+        }
 
-                // Update quantity
-                quantity -= trade_qty;
-                maker_order.quantity -= trade_qty;
+        // IOC (dan FOK yang lolos pengecekan) tidak pernah masuk buku
+        if !options.time_in_force.rests() {
+            events.push(EngineEvent::OrderUnfilled { id: order_id, user_id, side, quantity });
+            return events;
+        }
 
-                // Jika maker order habis, hapus dari buku
-                if maker_order.quantity == 0 {
-                    order_queue.pop_front();
-                    self.order_store.remove(maker_idx);
-                }
+        // 2. Placement Process (Maker Phase)
+        // Iceberg: hanya slice pertama yang terlihat, sisanya disimpan sebagai reserve
+        let display_quantity = options.display_quantity
+            .filter(|&display| display > 0 && display < quantity)
+            .unwrap_or(0);
+        let visible = if display_quantity > 0 { display_quantity } else { quantity };
 
-                if quantity == 0 {
-                    break;
-                }
-            }
+        self.rest_order(Order {
+            id: order_id,
+            user_id,
+            price,
+            quantity: visible,
+            side,
+            timestamp: self.now, 
+            expires_at: options.time_in_force.expires_at(),
+            stp: options.stp,
+            display_quantity,
+            hidden_quantity: quantity - visible,
+        });
 
-            // Bersihkan entry harga jika queue kosong
-            if order_queue.is_empty() {
-                match side {
-                    Side::Bid => { self.asks.remove(&best_price); },
-                    Side::Ask => { self.bids.remove(&best_price); },
-                }
-            }
+        events.push(EngineEvent::OrderPlaced {
+            id: order_id,
+            user_id,
+            price,
+            quantity: visible,
+            side,
+        });
+
+        events
+    }
+
+    // Ubah harga/quantity order yang sedang resting tanpa mengganti order id.
+    // Quantity turun di harga yang sama: priority antrian dipertahankan.
+    // Ganti harga atau quantity naik: order pindah ke belakang antrian (dan bisa langsung match)
+    pub fn amend_order(
+        &mut self,
+        order_id: OrderId,
+        user_id: UserId,
+        new_price: Price,
+        new_quantity: Quantity
+    ) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+
+        let Some(&internal_idx) = self.order_index.get(&order_id) else {
+            events.push(EngineEvent::OrderRejected { id: order_id, user_id, reason: RejectReason::OrderNotFound });
+            return events;
+        };
+
+        let order = &mut self.order_store[internal_idx];
+
+        // Security Check + quantity 0 bukan amend (gunakan cancel)
+        let rejected = if order.user_id != user_id {
+            Some(RejectReason::NotOrderOwner)
+        } else if new_quantity == 0 {
+            Some(RejectReason::InvalidQuantity)
+        } else {
+            None
+        };
+        if let Some(reason) = rejected {
+            events.push(EngineEvent::OrderRejected { id: order_id, user_id, reason });
+            return events;
         }
 
-        // 2. Placement Process (Maker Phase)
-        if quantity > 0 {
-            let new_order = Order {
+        let side = order.side;
+
+        // 1. Quantity turun (atau sama) di harga yang sama: update in-place
+        if new_price == order.price && new_quantity <= order.total_quantity() {
+            // Iceberg: kurangi reserve tersembunyi dulu, baru slice yang terlihat
+            let visible = std::cmp::min(order.quantity, new_quantity);
+            order.hidden_quantity = new_quantity - visible;
+            order.quantity = visible;
+
+            events.push(EngineEvent::OrderAmended {
                 id: order_id,
                 user_id,
-                price,
-                quantity,
                 side,
-                timestamp: 0, 
-            };
+                price: new_price,
+                quantity: visible,
+                priority_kept: true,
+            });
+            return events;
+        }
 
-            // Simpan ke Slab
-            let idx = self.order_store.insert(new_order.clone());
+        // 2. Priority hilang: lepas dari antrian lama, lalu masuk lagi seperti order baru
+        let mut order = self.unlink_order(internal_idx);
 
-            // Simpan mapping ID eksternal ke Internal Index
-            self.order_index.insert(order_id, idx);
+        // Harga baru bisa crossing spread, jadi wajib lewat Taker Phase agar buku tidak crossed
+        let taker = Taker { id: order_id, user_id, side, stp: order.stp, budget: None, display_quantity: order.display_quantity };
+        let remaining = self.match_incoming(&taker, Some(new_price), new_quantity, &mut events);
 
-            // Masukkan index ke queue yang sesuai
-            let queue = match side {
-                Side::Bid => self.bids.entry(price).or_insert_with(VecDeque::new),
-                Side::Ask => self.asks.entry(price).or_insert_with(VecDeque::new),
+        let mut visible = 0;
+        if remaining > 0 {
+            visible = match order.display_quantity {
+                0 => remaining,
+                display => std::cmp::min(display, remaining),
             };
-            queue.push_back(idx);
+            order.price = new_price;
+            order.quantity = visible;
+            order.hidden_quantity = remaining - visible;
+            order.timestamp = self.now;
+            self.rest_order(order);
+        }
+
+        events.push(EngineEvent::OrderAmended {
+            id: order_id,
+            user_id,
+            side,
+            price: new_price,
+            quantity: visible,
+            priority_kept: false,
+        });
+
+        self.activate_stops(&mut events);
+
+        events
+    }
+
+    // Simpan Stop / Stop-Limit order ke Trigger Store.
+    // Jika harga trade terakhir sudah melewati trigger, langsung diaktifkan
+    pub fn place_stop_order(&mut self, stop: StopOrder) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+
+        let already_triggered = match (stop.side, self.last_trade_price) {
+            (Side::Bid, Some(last)) => last >= stop.trigger_price,
+            (Side::Ask, Some(last)) => last <= stop.trigger_price,
+            (_, None) => false,
+        };
+
+        if already_triggered {
+            self.trigger_stop(stop, &mut events);
+            self.activate_stops(&mut events);
+            return events;
+        }
+
+        events.push(EngineEvent::StopPlaced {
+            id: stop.id,
+            user_id: stop.user_id,
+            side: stop.side,
+            trigger_price: stop.trigger_price,
+            limit_price: stop.limit_price,
+            quantity: stop.quantity,
+        });
+        self.stops.insert(stop);
+
+        events
+    }
+
+    pub fn has_stop(&self, order_id: OrderId) -> bool {
+        self.stops.contains(order_id)
+    }
+
+    // Stop order yang masih menunggu trigger
+    pub fn stop(&self, order_id: OrderId) -> Option<&StopOrder> {
+        self.stops.get(order_id)
+    }
+
+    // Order yang sedang resting di buku
+    pub fn order(&self, order_id: OrderId) -> Option<&Order> {
+        let &idx = self.order_index.get(&order_id)?;
+        self.order_store.get(idx)
+    }
+
+    // Pemilik order yang masih hidup (di buku atau Trigger Store)
+    pub fn owner(&self, order_id: OrderId) -> Option<UserId> {
+        self.order(order_id)
+            .map(|order| order.user_id)
+            .or_else(|| self.stops.get(order_id).map(|stop| stop.user_id))
+    }
+
+    pub fn is_live(&self, order_id: OrderId) -> bool {
+        self.order_index.contains_key(&order_id) || self.stops.contains(order_id)
+    }
+
+    // Aktifkan semua stop yang ter-trigger oleh trade sejak aktivasi terakhir.
+    // Eksekusi stop bisa menghasilkan trade baru, jadi diulang sampai tidak ada trigger lagi
+    fn activate_stops(&mut self, events: &mut Vec<EngineEvent>) {
+        while let Some((low, high)) = self.trade_range.take() {
+            for stop in self.stops.take_triggered(low, high) {
+                self.trigger_stop(stop, events);
+            }
+        }
+    }
+
+    // Ubah stop menjadi order aktif: Stop-Market sebagai market order, Stop-Limit sebagai limit GTC
+    fn trigger_stop(&mut self, stop: StopOrder, events: &mut Vec<EngineEvent>) {
+        events.push(EngineEvent::StopTriggered {
+            id: stop.id,
+            user_id: stop.user_id,
+            side: stop.side,
+            trigger_price: stop.trigger_price,
+        });
+
+        let mut order_events = match stop.limit_price {
+            Some(limit_price) => {
+                let options = OrderOptions { stp: stop.stp, ..Default::default() };
+                self.execute_order(stop.id, stop.user_id, stop.side, limit_price, stop.quantity, options)
+            }
+            None => self.execute_market_order(stop.id, stop.user_id, stop.side, stop.quantity, stop.stp, stop.budget),
+        };
+
+        events.append(&mut order_events);
+    }
+
+    // Simpan order ke Slab, index, dan belakang antrian level harganya
+    fn rest_order(&mut self, order: Order) {
+        let (order_id, side, price) = (order.id, order.side, order.price);
+
+        if let Some(expires_at) = order.expires_at {
+            self.expiries.insert((expires_at, order_id));
+        }
+
+        // Simpan ke Slab
+        let idx = self.order_store.insert(order);
+
+        // Simpan mapping ID eksternal ke Internal Index
+        self.order_index.insert(order_id, idx);
 
-            events.push(EngineEvent::OrderPlaced {
+        // Masukkan index ke queue yang sesuai
+        let queue = match side {
+            Side::Bid => self.bids.entry(price).or_default(),
+            Side::Ask => self.asks.entry(price).or_default(),
+        };
+        queue.push_back(idx);
+    }
+
+    // Lepas order dari antrian level harga, index, dan Slab. Mengembalikan order-nya
+    fn unlink_order(&mut self, internal_idx: usize) -> Order {
+        let order = self.order_store.remove(internal_idx);
+
+        // Hapus dari Queue (Agak tricky karena VecDeque)
+        let levels = match order.side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+
+        if let Some(q) = levels.get_mut(&order.price) {
+            // O(N) operation pada queue specific price level
+            // Ini acceptable karena biasanya satu level harga tidak memiliki jutaan order
+            // retain adalah cara terbersih menghapus item tertentu
+            q.retain(|&idx| idx != internal_idx);
+
+            // Jika queue kosong, hapus entry harga dari BTreeMap agar hemat memori
+            if q.is_empty() {
+                levels.remove(&order.price);
+            }
+        }
+
+        self.order_index.remove(&order.id);
+
+        if let Some(expires_at) = order.expires_at {
+            self.expiries.remove(&(expires_at, order.id));
+        }
+
+        order
+    }
+
+    // Harga terbaik sisi lawan jika order pada `price` akan langsung crossing spread
+    fn crossing_price(&self, side: Side, price: Price) -> Option<Price> {
+        match side {
+            Side::Bid => self.asks.keys().next().copied().filter(|&best| best <= price),
+            Side::Ask => self.bids.keys().next_back().copied().filter(|&best| best >= price),
+        }
+    }
+
+    // Maker yang akan ditemui taker (urut prioritas, sampai harga limit), mengikuti match_incoming:
+    // GTD maker yang sudah kedaluwarsa dibuang (tidak terisi), order milik sendiri dilewati hanya jika
+    // STP cukup membuang maker. Mode lain memotong/membatalkan taker, jadi sweep berhenti di situ
+    fn matchable_makers<'a>(&'a self, taker: &'a Taker, limit: Option<Price>) -> impl Iterator<Item = &'a Order> + 'a {
+        let levels: Box<dyn Iterator<Item = (&Price, &VecDeque<usize>)>> = match (taker.side, limit) {
+            (Side::Bid, Some(price)) => Box::new(self.asks.range(..=price)),
+            (Side::Bid, None) => Box::new(self.asks.iter()),
+            (Side::Ask, Some(price)) => Box::new(self.bids.range(price..).rev()),
+            (Side::Ask, None) => Box::new(self.bids.iter().rev()),
+        };
+
+        levels
+            .flat_map(|(_, queue)| queue.iter().map(|&idx| &self.order_store[idx]))
+            .filter(|order| order.expires_at.is_none_or(|expires_at| expires_at > self.now))
+            .take_while(|order| !taker.is_same_owner(order) || taker.stp.mode == SelfTradePrevention::CancelMaker)
+            .filter(|order| !taker.is_same_owner(order))
+    }
+
+    // Hitung quantity lawan yang bisa di-match pada harga limit (read-only).
+    // Berhenti lebih awal begitu `needed` sudah tercapai
+    fn matchable_quantity(&self, taker: &Taker, price: Price, needed: Quantity) -> Quantity {
+        let mut available: Quantity = 0;
+        for order in self.matchable_makers(taker, Some(price)) {
+            // Reserve iceberg ikut dihitung karena tetap bisa terisi
+            available += order.total_quantity();
+            if available >= needed {
+                break;
+            }
+        }
+        available
+    }
+
+    // Notional untuk menyapu `quantity` dengan market order di buku saat ini (read-only).
+    // Batas atas dana yang perlu dikunci market buy: liquidity yang tidak ada tidak perlu dibayar
+    pub fn sweep_cost(&self, user_id: UserId, side: Side, quantity: Quantity, stp: StpPolicy) -> u128 {
+        let taker = Taker { id: 0, user_id, side, stp, budget: None, display_quantity: 0 };
+        let mut remaining = quantity;
+        let mut cost: u128 = 0;
+        for order in self.matchable_makers(&taker, None) {
+            if remaining == 0 {
+                break;
+            }
+            let fill = std::cmp::min(remaining, order.total_quantity());
+            cost += fill as u128 * order.price as u128;
+            remaining -= fill;
+        }
+        cost
+    }
+
+    // Market Order: sapu sisi lawan sampai terisi penuh atau buku kosong.
+    // Tidak pernah masuk buku, sisa yang tidak terisi dilaporkan lewat OrderUnfilled
+    pub fn place_market_order(
+        &mut self,
+        order_id: OrderId,
+        user_id: UserId,
+        side: Side,
+        quantity: Quantity,
+        stp: StpPolicy
+    ) -> Vec<EngineEvent> {
+        self.place_market_order_with_budget(order_id, user_id, side, quantity, stp, None)
+    }
+
+    // Market Order dengan batas total quote (`budget`) yang boleh dibelanjakan.
+    // Begitu budget tidak cukup untuk 1 unit di harga berikutnya, sisa dilaporkan sebagai OrderUnfilled
+    pub fn place_market_order_with_budget(
+        &mut self,
+        order_id: OrderId,
+        user_id: UserId,
+        side: Side,
+        quantity: Quantity,
+        stp: StpPolicy,
+        budget: Option<u64>
+    ) -> Vec<EngineEvent> {
+        let mut events = self.execute_market_order(order_id, user_id, side, quantity, stp, budget);
+        self.activate_stops(&mut events);
+        events
+    }
+
+    fn execute_market_order(
+        &mut self,
+        order_id: OrderId,
+        user_id: UserId,
+        side: Side,
+        quantity: Quantity,
+        stp: StpPolicy,
+        budget: Option<u64>
+    ) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+
+        let taker = Taker { id: order_id, user_id, side, stp, budget, display_quantity: 0 };
+        let remaining = self.match_incoming(&taker, None, quantity, &mut events);
+
+        if remaining > 0 {
+            events.push(EngineEvent::OrderUnfilled {
                 id: order_id,
                 user_id,
-                price,
-                quantity,
                 side,
+                quantity: remaining,
             });
         }
 
         events
     }
 
+    // Mencoba mencocokkan order yang masuk dengan order yang ada di buku.
+    // `limit` = None berarti market order (tanpa batas harga).
+    // Mengembalikan sisa quantity yang belum terisi (0 jika taker dibatalkan oleh STP)
+    fn match_incoming(
+        &mut self,
+        taker: &Taker,
+        limit: Option<Price>,
+        mut quantity: Quantity,
+        events: &mut Vec<EngineEvent>
+    ) -> Quantity {
+        let side = taker.side;
+        let mut budget = taker.budget;
+
+        loop {
+            if quantity == 0 {
+                break;
+            }
+
+            // Cari order lawan terbaik (Best Bid or Best Ask)
+            let best_match_option = match side {
+                Side::Bid => self.asks.iter_mut().next(),
+                Side::Ask => self.bids.iter_mut().next_back(), 
+            };
+
+            // Jika tidak ada liquidity, stop matching
+            let (best_price, order_queue) = match best_match_option {
+                Some((p, q)) => (*p, q),
+                None => break,
+            };
+
+            // Cek apakah harga memenuhi syarat
+            // Bid: beli jika harga lawan <= harga limit saya
+            // Ask: jual jika harga lawan >= harga limit saya
+            let is_matchable = match (side, limit) {
+                (_, None) => true,
+                (Side::Bid, Some(price)) => best_price <= price,
+                (Side::Ask, Some(price)) => best_price >= price,
+            };
+
+            if !is_matchable {
+                break;
+            }
+
+            let mut budget_exhausted = false;
+
+            // Proses queue pada harga terbaik
+            while let Some(&maker_idx) = order_queue.front() {
+                // Ambil referensi mutable ke maker order
+                let maker_order = self.order_store.get_mut(maker_idx).expect("Stale index in queue");
+
+                // GTD maker yang sudah kedaluwarsa tapi belum tersapu: keluarkan, jangan di-match
+                if maker_order.expires_at.is_some_and(|expires_at| expires_at <= self.now) {
+                    order_queue.pop_front();
+
+                    events.push(maker_order.expired());
+
+                    if let Some(expires_at) = maker_order.expires_at {
+                        self.expiries.remove(&(expires_at, maker_order.id));
+                    }
+                    self.order_index.remove(&maker_order.id);
+                    self.order_store.remove(maker_idx);
+                    continue;
+                }
+
+                // Self-Trade Prevention 
+                if taker.is_same_owner(maker_order) {
+                    let mode = taker.stp.mode;
+
+                    // Decrement: kurangi kedua sisi tanpa trade
+                    if mode == SelfTradePrevention::DecrementAndCancel {
+                        let decrement = std::cmp::min(quantity, maker_order.quantity);
+                        events.push(EngineEvent::SelfTradeDecremented {
+                            maker_id: maker_order.id,
+                            taker_id: taker.id,
+                            quantity: decrement,
+                        });
+                        quantity -= decrement;
+                        maker_order.quantity -= decrement;
+
+                        // Iceberg maker: slice habis tapi reserve masih ada, isi ulang
+                        if maker_order.quantity == 0 && maker_order.replenish() {
+                            events.push(EngineEvent::OrderReplenished {
+                                id: maker_order.id,
+                                side: maker_order.side,
+                                price: best_price,
+                                quantity: maker_order.quantity,
+                            });
+                            order_queue.pop_front();
+                            order_queue.push_back(maker_idx);
+                        }
+                    }
+
+                    // Cancel Maker (Resting Order dibuang)
+                    let cancel_maker = match mode {
+                        SelfTradePrevention::CancelMaker | SelfTradePrevention::CancelBoth => true,
+                        SelfTradePrevention::CancelTaker => false,
+                        SelfTradePrevention::DecrementAndCancel => maker_order.quantity == 0,
+                    };
+
+                    if cancel_maker {
+                        // Agar loop tidak macet, sebaiknya harus pop order ini.
+                        order_queue.pop_front();
+
+                        events.push(maker_order.cancelled(CancelReason::SelfTradePrevention));
+
+                        // Hapus dari Index & Slab
+                        if let Some(expires_at) = maker_order.expires_at {
+                            self.expiries.remove(&(expires_at, maker_order.id));
+                        }
+                        self.order_index.remove(&maker_order.id);
+                        self.order_store.remove(maker_idx);
+                    }
+
+                    // Cancel Taker: sisa order masuk tidak boleh lanjut matching atau masuk buku
+                    let cancel_taker = match mode {
+                        SelfTradePrevention::CancelMaker => false,
+                        SelfTradePrevention::CancelTaker | SelfTradePrevention::CancelBoth => true,
+                        SelfTradePrevention::DecrementAndCancel => quantity == 0,
+                    };
+
+                    if cancel_taker {
+                        events.push(EngineEvent::OrderCancelled {
+                            id: taker.id,
+                            user_id: taker.user_id,
+                            side,
+                            price: limit,
+                            quantity,
+                            displayed_quantity: taker.displayed(quantity),
+                            reason: CancelReason::SelfTradePrevention,
+                        });
+                        quantity = 0;
+                        break;
+                    }
+
+                    // Lanjut ke order berikutnya di antrian yang sama
+                    continue; 
+                }
+
+                // Hitung jumlah yang bisa di-trade
+                let mut trade_qty = std::cmp::min(quantity, maker_order.quantity);
+
+                // Market buy dengan budget: hanya ambil sebanyak yang masih bisa dibayar
+                if let Some(remaining_budget) = budget.as_mut() {
+                    trade_qty = std::cmp::min(trade_qty, remaining_budget.checked_div(best_price).unwrap_or(trade_qty));
+                    if trade_qty == 0 {
+                        budget_exhausted = true;
+                        break;
+                    }
+                    *remaining_budget -= trade_qty * best_price;
+                }
+
+                // Emit Trade Event
+                events.push(EngineEvent::TradeExecuted {
+                    maker_id: maker_order.id, 
+                    taker_id: taker.id, 
+                    maker_user_id: maker_order.user_id,
+                    taker_user_id: taker.user_id,
+                    price: best_price,
+                    quantity: trade_qty,
+                    maker_fee: 0,
+                    taker_fee: 0,
+                });
+
+                // Update quantity
+                quantity -= trade_qty;
+                maker_order.quantity -= trade_qty;
+
+                // Catat harga trade untuk Trigger Store (stop order)
+                self.last_trade_price = Some(best_price);
+                self.trade_range = Some(match self.trade_range {
+                    Some((low, high)) => (low.min(best_price), high.max(best_price)),
+                    None => (best_price, best_price),
+                });
+
+                // Jika slice maker habis: iceberg diisi ulang dan pindah ke belakang antrian,
+                // order biasa dihapus dari buku
+                if maker_order.quantity == 0 {
+                    order_queue.pop_front();
+
+                    if maker_order.replenish() {
+                        events.push(EngineEvent::OrderReplenished {
+                            id: maker_order.id,
+                            side: maker_order.side,
+                            price: best_price,
+                            quantity: maker_order.quantity,
+                        });
+                        order_queue.push_back(maker_idx);
+                    } else {
+                        if let Some(expires_at) = maker_order.expires_at {
+                            self.expiries.remove(&(expires_at, maker_order.id));
+                        }
+                        self.order_index.remove(&maker_order.id);
+                        self.order_store.remove(maker_idx);
+                    }
+                }
+
+                if quantity == 0 {
+                    break;
+                }
+            }
+
+            // Bersihkan entry harga jika queue kosong
+            if order_queue.is_empty() {
+                match side {
+                    Side::Bid => { self.asks.remove(&best_price); },
+                    Side::Ask => { self.bids.remove(&best_price); },
+                }
+            }
+
+            if budget_exhausted {
+                break;
+            }
+        }
+
+        quantity
+    }
+
+    // Mass cancel (kill switch risk system): keluarkan semua order dan stop milik user yang cocok dengan filter.
+    // Diurutkan per order id agar deterministik, diakhiri satu event ringkasan
+    pub fn cancel_all(&mut self, user_id: UserId, filter: CancelFilter) -> Vec<EngineEvent> {
+        let mut order_ids: Vec<OrderId> = self.order_store
+            .iter()
+            .filter(|(_, order)| order.user_id == user_id && filter.matches(order.side, order.price))
+            .map(|(_, order)| order.id)
+            .collect();
+        order_ids.sort_unstable();
+
+        let mut stop_ids: Vec<OrderId> = self.stops
+            .iter()
+            .filter(|stop| stop.user_id == user_id && filter.matches(stop.side, stop.limit_price.unwrap_or(stop.trigger_price)))
+            .map(|stop| stop.id)
+            .collect();
+        stop_ids.sort_unstable();
+
+        let mut events = Vec::with_capacity(order_ids.len() + stop_ids.len() + 1);
+        for order_id in order_ids {
+            let internal_idx = self.order_index[&order_id];
+            let order = self.unlink_order(internal_idx);
+            events.push(order.cancelled(CancelReason::Admin));
+        }
+        for order_id in stop_ids {
+            if let Some(stop) = self.stops.remove(order_id) {
+                events.push(stop.cancelled(CancelReason::Admin));
+            }
+        }
+
+        events.push(EngineEvent::MassCancelled { user_id, count: events.len() as u64 });
+        events
+    }
+
     pub fn cancel_order(&mut self, order_id: OrderId, user_id: UserId) -> Vec<EngineEvent> {
         let mut events = Vec::new();
 
+        // 0. Bukan order di buku? Mungkin stop order di Trigger Store
+        if !self.order_index.contains_key(&order_id) {
+            match self.stops.get(order_id).map(|stop| stop.user_id) {
+                Some(owner) if owner == user_id => {
+                    if let Some(stop) = self.stops.remove(order_id) {
+                        events.push(stop.cancelled(CancelReason::UserRequested));
+                    }
+                }
+                Some(_) => events.push(EngineEvent::OrderRejected { id: order_id, user_id, reason: RejectReason::NotOrderOwner }),
+                None => events.push(EngineEvent::OrderRejected { id: order_id, user_id, reason: RejectReason::OrderNotFound }),
+            }
+            return events;
+        }
+
         // 1. Cek apakah order ada di index
         if let Some(&internal_idx) = self.order_index.get(&order_id) {
 
@@ -251,43 +1403,15 @@ impl OrderBook {
                 // 3. Security Check: Apakah ini order milik user yang request?
                 if order.user_id != user_id {
                     // Unauthorized cancel attempt
-                    return events; 
+                    events.push(EngineEvent::OrderRejected { id: order_id, user_id, reason: RejectReason::NotOrderOwner });
+                    return events;
                 }
 
-                let price = order.price;
-                let side = order.side;
-                let _remaining_qty = order.quantity;
-
-                // 4. Hapus dari Queue (Agak tricky karena VecDeque)
-                // Mencari index di dalam queue harga tersebut
-                let queue = match side {
-                    Side::Bid => self.bids.get_mut(&price),
-                    Side::Ask => self.asks.get_mut(&price),
-                };
-
-                if let Some(q) = queue {
-                    // O(N) operation pada queue specific price level
-                    // Ini acceptable karena biasanya satu level harga tidak memiliki jutaan order
-                    // retain adalah cara terbersih menghapus item tertentu
-                    q.retain(|&idx| idx != internal_idx);
-
-                    // Jika queue kosong, hapus entry harga dari BTreeMap agar hemat memori
-                    if q.is_empty() {
-                        match side {
-                            Side::Bid => { self.bids.remove(&price); },
-                            Side::Ask => { self.asks.remove(&price); },
-                        }
-                    }
-                }
+                // 4. Hapus dari Queue, Index Mapping, dan Memory Slab
+                let order = self.unlink_order(internal_idx);
 
-                // 5. Hapus dari Index Mapping
-                self.order_index.remove(&order_id);
-
-                // 6. Hapus dari Memory Slab
-                self.order_store.remove(internal_idx);
-
-                // 7. Emit Event Success
-                events.push(EngineEvent::OrderCancelled { id: order_id });
+                // 5. Emit Event Success (beserta sisa quantity yang dibatalkan)
+                events.push(order.cancelled(CancelReason::UserRequested));
             }
         }
 
@@ -326,6 +1450,7 @@ impl OrderBook {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_support::place_entry;
 
     #[test]
     fn test_limit_order_placement_no_match() {
@@ -348,7 +1473,7 @@ mod tests {
 
         let trade_event = events.iter().find(|e| matches!(e, EngineEvent::TradeExecuted {..}));
 
-        if let EngineEvent::TradeExecuted {maker_id, taker_id, price, quantity} = trade_event.unwrap() {
+        if let EngineEvent::TradeExecuted {maker_id, taker_id, price, quantity, ..} = trade_event.unwrap() {
             assert_eq!(*maker_id, 1);
             assert_eq!(*taker_id, 2);
             assert_eq!(*price, 100);
@@ -365,6 +1490,61 @@ mod tests {
         assert!(events.iter().any(|e| matches!(e, EngineEvent::TradeExecuted {quantity: 10, ..})));
     }
 
+    #[test]
+    fn test_cancel_reports_remaining_quantity() {
+        let mut book = OrderBook::new();
+        let iceberg = OrderOptions { display_quantity: Some(5), ..Default::default() };
+        book.place_order(1, 1, Side::Ask, 100, 20, iceberg);
+        book.place_limit_order(2, 2, Side::Bid, 100, 8);
+
+        // Sisa = slice terlihat (2 dari slice kedua) + reserve tersembunyi, publik hanya melihat slice
+        let events = book.cancel_order(1, 1);
+        assert!(matches!(events[..], [EngineEvent::OrderCancelled {
+            id: 1, user_id: 1, side: Side::Ask, price: Some(100), quantity: 12, displayed_quantity: 2, reason: CancelReason::UserRequested
+        }]));
+
+        // STP: maker dibuang dengan alasan STP
+        book.place_limit_order(3, 3, Side::Ask, 101, 7);
+        let events = book.place_limit_order(4, 3, Side::Bid, 101, 7);
+        assert!(matches!(events[0], EngineEvent::OrderCancelled {
+            id: 3, quantity: 7, reason: CancelReason::SelfTradePrevention, ..
+        }));
+    }
+
+    #[test]
+    fn test_mass_cancel_by_side_and_price_range() {
+        let mut book = OrderBook::new();
+        book.place_limit_order(1, 1, Side::Bid, 90, 5);
+        book.place_limit_order(2, 1, Side::Bid, 95, 5);
+        book.place_limit_order(3, 1, Side::Ask, 110, 5);
+        book.place_limit_order(4, 2, Side::Bid, 95, 5);
+        book.place_stop_order(stop(5, Side::Ask, 80, None, 5));
+
+        // Hanya bid user 1 di rentang 92..=100
+        let filter = CancelFilter { side: Some(Side::Bid), min_price: Some(92), max_price: Some(100) };
+        let events = book.cancel_all(1, filter);
+        assert!(matches!(events[..], [
+            EngineEvent::OrderCancelled { id: 2, quantity: 5, reason: CancelReason::Admin, .. },
+            EngineEvent::MassCancelled { user_id: 1, count: 1 },
+        ]));
+
+        // Tanpa filter: semua sisa order user 1, order user lain tidak tersentuh
+        let events = book.cancel_all(1, CancelFilter::default());
+        assert!(matches!(events[..], [
+            EngineEvent::OrderCancelled { id: 1, .. },
+            EngineEvent::OrderCancelled { id: 3, .. },
+            EngineEvent::MassCancelled { count: 2, .. },
+        ]));
+        assert!(book.is_live(4) && book.is_live(5));
+
+        // Stop order ikut dicancel (helper stop() dimiliki user = id)
+        let events = book.cancel_all(5, CancelFilter::default());
+        assert!(matches!(events[..], [
+            EngineEvent::StopCancelled { id: 5, reason: CancelReason::Admin, .. },
+            EngineEvent::MassCancelled { count: 1, .. },
+        ]));
+    }
+
     #[test]
     fn test_self_trade_prevention_cancel_maker() {
         let mut book = OrderBook::new();
@@ -377,5 +1557,840 @@ mod tests {
 
         let place_event = events.iter().find(|e| matches!(e, EngineEvent::OrderPlaced {..}));
         assert!(place_event.is_some(), "Taker order harusnya masuk book");
+
+        // Semua mode STP (CancelMaker di atas adalah default):
+        // (mode, maker_qty, taker_qty, maker tersisa di buku, taker masuk buku)
+        let cases = [
+            (SelfTradePrevention::CancelMaker, 10, 10, None, Some(10)),
+            (SelfTradePrevention::CancelTaker, 10, 10, Some(10), None),
+            (SelfTradePrevention::CancelBoth, 10, 10, None, None),
+            (SelfTradePrevention::DecrementAndCancel, 10, 4, Some(6), None),
+            (SelfTradePrevention::DecrementAndCancel, 4, 10, None, Some(6)),
+            (SelfTradePrevention::DecrementAndCancel, 10, 10, None, None),
+        ];
+
+        for (mode, maker_qty, taker_qty, maker_left, taker_rested) in cases {
+            let mut book = OrderBook::new();
+            let stp = StpPolicy { mode, group: None };
+            book.place_order(100, 1, Side::Ask, 100, maker_qty, OrderOptions { stp, ..Default::default() });
+            let events = book.place_order(200, 1, Side::Bid, 100, taker_qty, OrderOptions { stp, ..Default::default() });
+
+            assert!(!events.iter().any(|e| matches!(e, EngineEvent::TradeExecuted { .. })), "{:?}: tidak boleh ada trade", mode);
+
+            let maker_cancelled = events.iter().any(|e| matches!(e, EngineEvent::OrderCancelled { id: 100, .. }));
+            assert_eq!(maker_cancelled, maker_left.is_none(), "{:?}: status cancel maker", mode);
+
+            let (asks, bids) = book.get_depth(10);
+            assert_eq!(asks.first().map(|l| l.quantity), maker_left, "{:?}: sisa maker", mode);
+            assert_eq!(bids.first().map(|l| l.quantity), taker_rested, "{:?}: sisa taker", mode);
+        }
+    }
+
+    #[test]
+    fn test_market_order_sweeps_and_never_rests() {
+        let mut book = OrderBook::new();
+        book.place_limit_order(1, 1, Side::Ask, 100, 5);
+        book.place_limit_order(2, 2, Side::Ask, 101, 5);
+
+        let events = book.place_market_order(3, 3, Side::Bid, 15, StpPolicy::default());
+
+        let filled: u64 = events.iter()
+            .filter_map(|e| match e {
+                EngineEvent::TradeExecuted { quantity, .. } => Some(*quantity),
+                _ => None,
+            })
+            .sum();
+        assert_eq!(filled, 10);
+
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::OrderUnfilled { id: 3, quantity: 5, .. })));
+        assert!(!events.iter().any(|e| matches!(e, EngineEvent::OrderPlaced { .. })), "Market order tidak boleh masuk book");
+
+        let (asks, bids) = book.get_depth(10);
+        assert!(asks.is_empty());
+        assert!(bids.is_empty());
+    }
+
+    #[test]
+    fn test_ioc_fills_then_discards_remainder() {
+        let mut book = OrderBook::new();
+        book.place_limit_order(1, 1, Side::Ask, 100, 4);
+
+        let options = OrderOptions { time_in_force: TimeInForce::Ioc, ..Default::default() };
+        let events = book.place_order(2, 2, Side::Bid, 100, 10, options);
+
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::TradeExecuted { quantity: 4, .. })));
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::OrderUnfilled { id: 2, quantity: 6, .. })));
+        assert!(!events.iter().any(|e| matches!(e, EngineEvent::OrderPlaced { .. })), "IOC tidak boleh masuk book");
+
+        let (_, bids) = book.get_depth(10);
+        assert!(bids.is_empty());
+    }
+
+    #[test]
+    fn test_fok_killed_without_trades() {
+        let mut book = OrderBook::new();
+        book.place_limit_order(1, 1, Side::Ask, 100, 4);
+        book.place_limit_order(2, 1, Side::Ask, 105, 10); // Di luar limit price
+
+        let options = OrderOptions { time_in_force: TimeInForce::Fok, ..Default::default() };
+        let events = book.place_order(3, 2, Side::Bid, 100, 10, options);
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], EngineEvent::OrderKilled { id: 3, quantity: 10, .. }));
+
+        // Buku tidak berubah sama sekali
+        let (asks, _) = book.get_depth(10);
+        assert_eq!(asks[0].quantity, 4);
+
+        // Jika liquidity cukup, FOK terisi penuh
+        let events = book.place_order(4, 2, Side::Bid, 105, 14, options);
+        let filled: u64 = events.iter()
+            .filter_map(|e| match e {
+                EngineEvent::TradeExecuted { quantity, .. } => Some(*quantity),
+                _ => None,
+            })
+            .sum();
+        assert_eq!(filled, 14);
+
+        // Order milik sendiri di depan antrian: hanya CancelMaker yang masih bisa terisi penuh
+        let modes = [
+            (SelfTradePrevention::CancelMaker, true),
+            (SelfTradePrevention::CancelTaker, false),
+            (SelfTradePrevention::CancelBoth, false),
+            (SelfTradePrevention::DecrementAndCancel, false),
+        ];
+        for (mode, fillable) in modes {
+            let mut book = OrderBook::new();
+            book.place_limit_order(1, 1, Side::Ask, 100, 4);
+            book.place_limit_order(2, 2, Side::Ask, 100, 4);
+            book.place_limit_order(3, 1, Side::Ask, 100, 10);
+
+            let stp = StpPolicy { mode, group: None };
+            let options = OrderOptions { time_in_force: TimeInForce::Fok, stp, ..Default::default() };
+            let events = book.place_order(4, 2, Side::Bid, 100, 10, options);
+            let filled: u64 = events.iter()
+                .filter_map(|e| match e {
+                    EngineEvent::TradeExecuted { quantity, .. } => Some(*quantity),
+                    _ => None,
+                })
+                .sum();
+            if fillable {
+                assert_eq!(filled, 10, "{:?}", mode);
+            } else {
+                assert!(matches!(events[..], [EngineEvent::OrderKilled { id: 4, quantity: 10, .. }]), "{:?}: {:?}", mode, events);
+                assert_eq!(book.get_depth(10).0[0].quantity, 18, "{:?}: buku tidak berubah", mode);
+            }
+        }
+
+        // GTD maker yang sudah kedaluwarsa (belum tersapu) tidak ikut dihitung
+        let mut book = OrderBook::new();
+        let gtd = OrderOptions { time_in_force: TimeInForce::Gtd { expires_at: 500 }, ..Default::default() };
+        book.place_order(1, 1, Side::Ask, 100, 6, gtd);
+        book.place_limit_order(2, 1, Side::Ask, 100, 4);
+        book.advance_time(600);
+        let events = book.place_order(3, 2, Side::Bid, 100, 10, options);
+        assert!(matches!(events[..], [EngineEvent::OrderKilled { id: 3, quantity: 10, .. }]));
+        assert_eq!(book.get_depth(10).0[0].quantity, 10);
+    }
+
+    #[test]
+    fn test_post_only_reject_and_slide() {
+        let mut book = OrderBook::new();
+        book.place_limit_order(1, 1, Side::Ask, 100, 10);
+
+        // Reject: order akan crossing, tidak ada trade sama sekali
+        let reject = OrderOptions { post_only: PostOnly::Reject, ..Default::default() };
+        let events = book.place_order(2, 2, Side::Bid, 101, 5, reject);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], EngineEvent::PostOnlyRejected { id: 2, .. }));
+
+        // Tidak crossing: masuk book seperti biasa
+        let events = book.place_order(3, 2, Side::Bid, 99, 5, reject);
+        assert!(matches!(events[0], EngineEvent::OrderPlaced { id: 3, price: 99, .. }));
+
+        // Slide: harga digeser ke 1 tick di bawah best ask
+        let slide = OrderOptions { post_only: PostOnly::Slide, ..Default::default() };
+        let events = book.place_order(4, 2, Side::Bid, 105, 5, slide);
+        assert!(matches!(events[0], EngineEvent::PostOnlySlid { original_price: 105, price: 99, .. }));
+        assert!(matches!(events[1], EngineEvent::OrderPlaced { id: 4, price: 99, .. }));
+        assert!(!events.iter().any(|e| matches!(e, EngineEvent::TradeExecuted { .. })));
+
+        let (asks, bids) = book.get_depth(10);
+        assert_eq!(asks[0].quantity, 10);
+        assert_eq!(bids[0].quantity, 10);
+    }
+
+    #[test]
+    fn test_amend_quantity_down_keeps_priority() {
+        let mut book = OrderBook::new();
+        book.place_limit_order(1, 1, Side::Ask, 100, 10);
+        book.place_limit_order(2, 2, Side::Ask, 100, 10);
+
+        let events = book.amend_order(1, 1, 100, 4);
+        assert!(matches!(events[0], EngineEvent::OrderAmended { id: 1, quantity: 4, priority_kept: true, .. }));
+
+        // Order 1 masih di depan antrian
+        let events = book.place_limit_order(3, 3, Side::Bid, 100, 4);
+        assert!(matches!(events[0], EngineEvent::TradeExecuted { maker_id: 1, quantity: 4, .. }));
+    }
+
+    #[test]
+    fn test_amend_quantity_up_or_price_change_loses_priority() {
+        let mut book = OrderBook::new();
+        book.place_limit_order(1, 1, Side::Ask, 100, 10);
+        book.place_limit_order(2, 2, Side::Ask, 100, 10);
+
+        let events = book.amend_order(1, 1, 100, 15);
+        assert!(matches!(events[0], EngineEvent::OrderAmended { id: 1, quantity: 15, priority_kept: false, .. }));
+
+        // Order 2 sekarang di depan
+        let events = book.place_limit_order(3, 3, Side::Bid, 100, 10);
+        assert!(matches!(events[0], EngineEvent::TradeExecuted { maker_id: 2, .. }));
+
+        // Ganti harga sampai crossing: langsung match dengan bid yang ada
+        book.place_limit_order(4, 4, Side::Bid, 98, 5);
+        let events = book.amend_order(1, 1, 98, 15);
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::TradeExecuted { maker_id: 4, taker_id: 1, quantity: 5, .. })));
+
+        let (asks, bids) = book.get_depth(10);
+        assert!(bids.is_empty());
+        assert_eq!(asks[0].price, 98);
+        assert_eq!(asks[0].quantity, 10);
+
+        // Unauthorized amend ditolak tanpa mengubah buku
+        let events = book.amend_order(1, 2, 99, 1);
+        assert!(matches!(events[..], [EngineEvent::OrderRejected { id: 1, reason: RejectReason::NotOrderOwner, .. }]));
+        assert_eq!(book.order(1).map(|o| o.price), Some(98));
+    }
+
+    #[test]
+    fn test_self_trade_prevention_group() {
+        let mut book = OrderBook::new();
+        let group = StpPolicy { mode: SelfTradePrevention::CancelTaker, group: Some(7) };
+
+        // User 1 & 2 adalah sub-account dari owner yang sama (group 7)
+        book.place_order(1, 1, Side::Ask, 100, 10, OrderOptions { stp: group, ..Default::default() });
+        let events = book.place_order(2, 2, Side::Bid, 100, 10, OrderOptions { stp: group, ..Default::default() });
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::OrderCancelled { id: 2, .. })));
+        assert!(!events.iter().any(|e| matches!(e, EngineEvent::TradeExecuted { .. })));
+
+        // User lain di luar group tetap bisa trade
+        let events = book.place_limit_order(3, 3, Side::Bid, 100, 10);
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::TradeExecuted { maker_id: 1, .. })));
+    }
+
+    fn stop(id: OrderId, side: Side, trigger_price: Price, limit_price: Option<Price>, quantity: Quantity) -> StopOrder {
+        StopOrder { id, user_id: id, side, trigger_price, limit_price, quantity, stp: StpPolicy::default(), budget: None }
+    }
+
+    #[test]
+    fn test_stop_orders_trigger_in_deterministic_order() {
+        let mut book = OrderBook::new();
+        book.place_limit_order(1, 1, Side::Ask, 100, 5);
+        book.place_limit_order(2, 2, Side::Ask, 105, 5);
+        book.place_limit_order(3, 3, Side::Ask, 110, 20);
+
+        // Stop tidak terlihat di depth
+        book.place_stop_order(stop(10, Side::Bid, 104, None, 3));
+        book.place_stop_order(stop(11, Side::Bid, 101, Some(105), 2));
+        assert_eq!(book.get_depth(10).1.len(), 0);
+        assert!(book.has_stop(10) && book.has_stop(11));
+
+        // Trade di 100 dan 105 men-trigger kedua stop, urut berdasarkan kedatangan
+        let events = book.place_limit_order(4, 4, Side::Bid, 105, 8);
+        let triggered: Vec<OrderId> = events.iter()
+            .filter_map(|e| match e {
+                EngineEvent::StopTriggered { id, .. } => Some(*id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(triggered, vec![10, 11]);
+        assert!(!book.has_stop(10) && !book.has_stop(11));
+
+        // Stop-Market 10 mengambil 2 sisa di 105 lalu 1 di 110; Stop-Limit 11 @105 masuk buku
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::TradeExecuted { taker_id: 10, price: 110, quantity: 1, .. })));
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::OrderPlaced { id: 11, price: 105, quantity: 2, .. })));
+    }
+
+    #[test]
+    fn test_stop_cancel_and_sell_trigger() {
+        let mut book = OrderBook::new();
+        book.place_limit_order(1, 1, Side::Bid, 100, 10);
+        book.place_stop_order(stop(20, Side::Ask, 100, None, 4));
+        book.place_stop_order(stop(21, Side::Ask, 99, None, 4));
+
+        // Cancel oleh user lain ditolak, order yang tidak ada juga
+        let events = book.cancel_order(21, 99);
+        assert!(matches!(events[..], [EngineEvent::OrderRejected { id: 21, reason: RejectReason::NotOrderOwner, .. }]));
+        let events = book.cancel_order(999, 21);
+        assert!(matches!(events[..], [EngineEvent::OrderRejected { id: 999, reason: RejectReason::OrderNotFound, .. }]));
+        let events = book.cancel_order(21, 21);
+        assert!(matches!(events[0], EngineEvent::StopCancelled { id: 21, quantity: 4, reason: CancelReason::UserRequested, .. }));
+
+        // Trade di 100 men-trigger sell stop 20 (trigger >= harga trade)
+        let events = book.place_limit_order(2, 2, Side::Ask, 100, 1);
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::StopTriggered { id: 20, .. })));
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::TradeExecuted { taker_id: 20, quantity: 4, .. })));
+        assert_eq!(book.get_depth(10).1[0].quantity, 5);
+    }
+
+    #[test]
+    fn test_engine_error_from_reject_events() {
+        use error::EngineError;
+        use ledger::Asset;
+
+        let mut book = OrderBook::new();
+        assert_eq!(EngineError::from_events(&book.place_limit_order(1, 1, Side::Bid, 100, 10)), None);
+
+        let error = EngineError::from_events(&book.cancel_order(999, 1)).unwrap();
+        assert_eq!(error, EngineError::OrderRejected { order_id: 999, reason: RejectReason::OrderNotFound });
+        assert_eq!(error.to_string(), "order 999 rejected: order not found");
+        let error = EngineError::from_events(&book.cancel_order(1, 2)).unwrap();
+        assert_eq!(error.reason(), RejectReason::NotOrderOwner);
+
+        let events = [EngineEvent::WithdrawalRejected { user_id: 1, asset: Asset::Quote, amount: 50, available: 20 }];
+        let error = EngineError::from_events(&events).unwrap();
+        assert_eq!(error.reason(), RejectReason::InsufficientBalance);
+        assert_eq!(error.to_string(), "withdrawal of 50 Quote rejected: only 20 available");
+        assert_eq!(EngineError::MarketHalted.reason(), RejectReason::MarketHalted);
+    }
+
+    #[test]
+    fn test_iceberg_hides_reserve_and_requeues() {
+        let mut book = OrderBook::new();
+        let iceberg = OrderOptions { display_quantity: Some(5), ..Default::default() };
+
+        let events = book.place_order(1, 1, Side::Ask, 100, 12, iceberg);
+        assert!(matches!(events[0], EngineEvent::OrderPlaced { id: 1, quantity: 5, .. }));
+        book.place_limit_order(2, 2, Side::Ask, 100, 3);
+
+        // Depth hanya menampilkan bagian yang terlihat (5 + 3)
+        assert_eq!(book.get_depth(10).0[0].quantity, 8);
+
+        // Slice pertama habis: diisi ulang dan pindah ke belakang order 2
+        let events = book.place_limit_order(3, 3, Side::Bid, 100, 5);
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::OrderReplenished { id: 1, quantity: 5, .. })));
+        assert_eq!(book.get_depth(10).0[0].quantity, 8);
+
+        let events = book.place_limit_order(4, 3, Side::Bid, 100, 4);
+        assert!(matches!(events[0], EngineEvent::TradeExecuted { maker_id: 2, quantity: 3, .. }));
+        assert!(matches!(events[1], EngineEvent::TradeExecuted { maker_id: 1, quantity: 1, .. }));
+
+        // Sisa 4 terlihat + 2 tersembunyi, FOK melihat total reserve
+        let fok = OrderOptions { time_in_force: TimeInForce::Fok, ..Default::default() };
+        let events = book.place_order(5, 3, Side::Bid, 100, 6, fok);
+        let filled: u64 = events.iter()
+            .filter_map(|e| match e {
+                EngineEvent::TradeExecuted { quantity, .. } => Some(*quantity),
+                _ => None,
+            })
+            .sum();
+        assert_eq!(filled, 6);
+        assert!(book.get_depth(10).0.is_empty());
+    }
+
+    #[test]
+    fn test_gtd_orders_expire_on_engine_clock() {
+        let mut book = OrderBook::new();
+        book.advance_time(1_000);
+
+        let gtd = OrderOptions { time_in_force: TimeInForce::Gtd { expires_at: 2_000 }, ..Default::default() };
+        book.place_order(1, 1, Side::Ask, 100, 10, gtd);
+        book.place_limit_order(2, 2, Side::Ask, 101, 10);
+        assert_eq!(book.next_expiry(), Some(2_000));
+
+        // Belum waktunya: tidak ada yang kedaluwarsa
+        assert!(book.expire_orders(1_999).is_empty());
+
+        let events = book.expire_orders(2_000);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], EngineEvent::OrderExpired { id: 1, quantity: 10, .. }));
+        assert_eq!(book.get_depth(10).0.len(), 1);
+        assert_eq!(book.next_expiry(), None);
+
+        // GTD yang dikirim setelah expires_at langsung kedaluwarsa tanpa trade
+        let events = book.place_order(3, 3, Side::Bid, 101, 5, gtd);
+        assert!(matches!(events[0], EngineEvent::OrderExpired { id: 3, .. }));
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_expired_maker_is_never_matched() {
+        let mut book = OrderBook::new();
+        let gtd = OrderOptions { time_in_force: TimeInForce::Gtd { expires_at: 500 }, ..Default::default() };
+        book.place_order(1, 1, Side::Ask, 100, 10, gtd);
+        book.place_limit_order(2, 2, Side::Ask, 100, 10);
+
+        // Waktu maju (dari entry WAL berikutnya) sebelum sweep sempat berjalan
+        book.advance_time(600);
+        let events = book.place_limit_order(3, 3, Side::Bid, 100, 5);
+
+        assert!(matches!(events[0], EngineEvent::OrderExpired { id: 1, .. }));
+        assert!(matches!(events[1], EngineEvent::TradeExecuted { maker_id: 2, quantity: 5, .. }));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_gtd_expiry_follows_processor_clock() {
+        use std::time::Duration;
+        use clock::ManualClock;
+        use ledger::Asset;
+        use processor::{Command, MarketProcessor};
+        use test_support::{deposit_command, market_config, place_command, TempDir};
+        use tokio::sync::{broadcast, mpsc, oneshot};
+        use wal::WalHandler;
+
+        let dir = TempDir::new("gtd_clock");
+        let config = market_config(&dir);
+        let clock = ManualClock::new(1_000);
+        let (tx, rx) = mpsc::channel(8);
+        let (broadcast_tx, mut events) = broadcast::channel(16);
+        let processor = MarketProcessor::with_clock(config.clone(), rx, broadcast_tx, Box::new(clock.clone())).unwrap();
+        tokio::spawn(processor.run());
+
+        let gtd = |order_id, expires_at| {
+            let (mut command, reply) = place_command(order_id, 1, Side::Ask, 100, 5);
+            if let Command::PlaceOrder { time_in_force, .. } = &mut command {
+                *time_in_force = TimeInForce::Gtd { expires_at };
+            }
+            (command, reply)
+        };
+        let depth = || async {
+            let (responder, depth) = oneshot::channel();
+            tx.send(Command::GetDepth { limit: 10, responder }).await.unwrap();
+            depth.await.unwrap().0.len()
+        };
+
+        for (command, reply) in [deposit_command(1, 1, Asset::Base, 10), gtd(1, 2_000)] {
+            tx.send(command).await.unwrap();
+            assert!(reply.await.unwrap().is_ok());
+        }
+
+        // Sweep berjalan, tapi jam engine belum sampai expires_at
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(depth().await, 1);
+
+        clock.set(2_000);
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(depth().await, 0);
+        let expired = std::iter::from_fn(|| events.try_recv().ok())
+            .any(|e| matches!(e.event, EngineEvent::OrderExpired { id: 1, .. }));
+        assert!(expired);
+
+        // GTD yang dikirim setelah expires_at langsung kedaluwarsa (timestamp entry dari clock yang sama)
+        clock.advance(1);
+        let (command, reply) = gtd(2, 2_000);
+        tx.send(command).await.unwrap();
+        assert!(reply.await.unwrap().is_ok());
+        assert_eq!(depth().await, 0);
+
+        // Waktu kedaluwarsa tercatat di WAL, jadi replay tidak bergantung pada jam
+        let recovery = WalHandler::recover(&config.wal_dir, 0, &[]).unwrap();
+        assert!(recovery.entries.iter().any(|(_, entry)| matches!(entry, LogEntry::Expire { timestamp: 2_000 })));
+        assert!(recovery.entries.iter().any(|(_, entry)| matches!(entry, LogEntry::Place { order_id: 2, timestamp: 2_001, .. })));
+    }
+
+    #[test]
+    fn test_instrument_spec_validation() {
+        use instrument::InstrumentSpec;
+
+        let spec = InstrumentSpec {
+            tick_size: 5,
+            lot_size: 10,
+            min_quantity: 20,
+            max_quantity: 1_000,
+            min_notional: 5_000,
+            ..Default::default()
+        };
+
+        assert_eq!(spec.validate_limit(100, 100), Ok(()));
+        assert_eq!(spec.validate_limit(0, 100), Err(RejectReason::InvalidPrice));
+        assert_eq!(spec.validate_limit(101, 100), Err(RejectReason::PriceNotOnTick));
+        assert_eq!(spec.validate_limit(100, 0), Err(RejectReason::InvalidQuantity));
+        assert_eq!(spec.validate_limit(100, 25), Err(RejectReason::QuantityNotOnLot));
+        assert_eq!(spec.validate_limit(100, 10), Err(RejectReason::QuantityBelowMinimum));
+        assert_eq!(spec.validate_limit(100, 2_000), Err(RejectReason::QuantityAboveMaximum));
+        assert_eq!(spec.validate_limit(5, 30), Err(RejectReason::NotionalBelowMinimum));
+
+        // Post-Only slide bergeser satu tick, bukan satu unit
+        let mut book = OrderBook::with_tick_size(5);
+        book.place_limit_order(1, 1, Side::Ask, 100, 10);
+        let slide = OrderOptions { post_only: PostOnly::Slide, ..Default::default() };
+        let events = book.place_order(2, 2, Side::Bid, 105, 10, slide);
+        assert!(matches!(events[0], EngineEvent::PostOnlySlid { price: 95, .. }));
+    }
+
+    #[test]
+    fn test_instrument_spec_from_config() {
+        use instrument::InstrumentSpec;
+
+        let spec: InstrumentSpec = "tick=5, lot=10,min_qty=20,min_notional=5000,base_decimals=9,quote_decimals=6".parse().unwrap();
+        assert_eq!(spec, InstrumentSpec {
+            tick_size: 5,
+            lot_size: 10,
+            min_quantity: 20,
+            min_notional: 5_000,
+            base_decimals: 9,
+            quote_decimals: 6,
+            ..Default::default()
+        });
+        assert_eq!("".parse::<InstrumentSpec>(), Ok(InstrumentSpec::default()));
+
+        assert!("tick=0".parse::<InstrumentSpec>().is_err());
+        assert!("min_qty=10,max_qty=5".parse::<InstrumentSpec>().is_err());
+        assert!("tick=abc".parse::<InstrumentSpec>().is_err());
+        assert!("step=5".parse::<InstrumentSpec>().is_err());
+    }
+
+    #[test]
+    fn test_ledger_lock_settle_release() {
+        use ledger::{Asset, Balance, Ledger, Reservation, required_funds};
+
+        let fees = fees::FeeSchedule::default();
+        let mut book = OrderBook::new();
+        let mut ledger = Ledger::new();
+        ledger.credit(1, Asset::Base, 10);
+        ledger.credit(2, Asset::Quote, 2_000);
+
+        let place = |book: &mut OrderBook, ledger: &mut Ledger, id, user_id, side, price, quantity| {
+            let (asset, amount) = required_funds(side, price, quantity, &fees);
+            let reservation = Reservation { order_id: id, user_id, asset, amount };
+            assert!(ledger.can_reserve(&reservation));
+            ledger.reserve(reservation);
+            let events = book.place_limit_order(id, user_id, side, price, quantity);
+            ledger.apply_events(&events, book, &fees);
+        };
+
+        // Lock on place
+        place(&mut book, &mut ledger, 1, 1, Side::Ask, 90, 10);
+        assert_eq!(ledger.balance(1, Asset::Base), Balance { available: 0, locked: 10 });
+
+        // Bid 100 x 15 mengunci 1500, terisi 10 @ 90 (price improvement), sisa 5 resting
+        place(&mut book, &mut ledger, 2, 2, Side::Bid, 100, 15);
+        assert_eq!(ledger.balance(1, Asset::Base), Balance { available: 0, locked: 0 });
+        assert_eq!(ledger.balance(1, Asset::Quote), Balance { available: 900, locked: 0 });
+        assert_eq!(ledger.balance(2, Asset::Base), Balance { available: 10, locked: 0 });
+        assert_eq!(ledger.balance(2, Asset::Quote), Balance { available: 500, locked: 600 });
+
+        // Saldo tidak cukup untuk order berikutnya
+        let (asset, amount) = required_funds(Side::Bid, 100, 6, &fees);
+        assert!(!ledger.can_reserve(&Reservation { order_id: 3, user_id: 2, asset, amount }));
+
+        // Release on cancel
+        let events = book.cancel_order(2, 2);
+        ledger.apply_events(&events, &book, &fees);
+        assert_eq!(ledger.balance(2, Asset::Quote), Balance { available: 1_100, locked: 0 });
+
+        // Market buy dengan budget: berhenti begitu budget habis
+        book.place_limit_order(4, 1, Side::Ask, 100, 20);
+        let events = book.place_market_order_with_budget(5, 2, Side::Bid, 20, StpPolicy::default(), Some(1_050));
+        assert!(matches!(events[0], EngineEvent::TradeExecuted { quantity: 10, .. }));
+        assert!(matches!(events[1], EngineEvent::OrderUnfilled { quantity: 10, .. }));
+    }
+
+    #[test]
+    fn test_stp_decrement_releases_reservation() {
+        use ledger::{Asset, Balance, Ledger, Reservation, required_funds};
+
+        let fees = fees::FeeSchedule::default();
+        let mut book = OrderBook::new();
+        let mut ledger = Ledger::new();
+        ledger.credit(1, Asset::Base, 10);
+        ledger.credit(1, Asset::Quote, 1_000);
+
+        let decrement = StpPolicy { mode: SelfTradePrevention::DecrementAndCancel, group: None };
+        for (id, side, quantity) in [(1, Side::Ask, 10), (2, Side::Bid, 4)] {
+            let (asset, amount) = required_funds(side, 100, quantity, &fees);
+            let reservation = Reservation { order_id: id, user_id: 1, asset, amount };
+            assert!(ledger.can_reserve(&reservation));
+            ledger.reserve(reservation);
+            let events = book.place_order(id, 1, side, 100, quantity, OrderOptions { stp: decrement, ..Default::default() });
+            ledger.apply_events(&events, &book, &fees);
+        }
+
+        // Maker ask tersisa 6, 4 base yang dikurangi STP kembali ke available. Taker bid habis, quote-nya dilepas
+        assert_eq!(book.order(1).map(|o| o.quantity), Some(6));
+        assert_eq!(ledger.reserved(1), 6);
+        assert_eq!(ledger.balance(1, Asset::Base), Balance { available: 4, locked: 6 });
+        assert_eq!(ledger.balance(1, Asset::Quote), Balance { available: 1_000, locked: 0 });
+    }
+
+    #[test]
+    fn test_fee_tiers_rebates_and_settlement() {
+        use fees::{FeeEngine, FeeSchedule, FeeTier};
+        use ledger::{Asset, Balance, Ledger, Reservation, FEE_ACCOUNT, required_funds};
+
+        // Maker rebate 2 bps, taker 10 bps. Volume >= 5000 turun ke taker 5 bps
+        let schedule = FeeSchedule::new(-2, 10)
+            .with_tier(FeeTier { min_volume: 5_000, maker_bps: -2, taker_bps: 5 });
+        assert!(schedule.is_valid());
+        assert!(!FeeSchedule::new(-20, 10).is_valid());
+
+        let mut fees = FeeEngine::new(schedule.clone());
+        let mut book = OrderBook::new();
+        let mut ledger = Ledger::new();
+        ledger.credit(1, Asset::Base, 100);
+        ledger.credit(2, Asset::Quote, 100_000);
+
+        let trade = |book: &mut OrderBook, ledger: &mut Ledger, fees: &mut FeeEngine, id, now| {
+            book.advance_time(now);
+            for (order_id, user_id, side) in [(id, 1, Side::Ask), (id + 1, 2, Side::Bid)] {
+                let (asset, amount) = required_funds(side, 1_000, 5, &schedule);
+                let reservation = Reservation { order_id, user_id, asset, amount };
+                assert!(ledger.can_reserve(&reservation));
+                ledger.reserve(reservation);
+                let mut events = book.place_limit_order(order_id, user_id, side, 1_000, 5);
+                fees.charge(&mut events, book.now());
+                ledger.apply_events(&events, book, &schedule);
+                if let Some(EngineEvent::TradeExecuted { maker_fee, taker_fee, .. }) = events.first() {
+                    return (*maker_fee, *taker_fee);
+                }
+            }
+            unreachable!("orders must cross");
+        };
+
+        // Trade pertama: notional 5000, belum ada volume -> tier dasar
+        assert_eq!(trade(&mut book, &mut ledger, &mut fees, 10, 1_000), (-1, 5));
+        assert_eq!(ledger.balance(1, Asset::Quote).available, 5_001);
+        assert_eq!(ledger.balance(2, Asset::Quote).available, 100_000 - 5_005);
+        assert_eq!(ledger.balance(2, Asset::Base).available, 5);
+
+        // Trade kedua: volume taker 5000 -> tier murah
+        assert_eq!(trade(&mut book, &mut ledger, &mut fees, 20, 2_000), (-1, 2));
+
+        // Di luar jendela rolling volume, kembali ke tier dasar
+        let later = 2_000 + schedule.volume_window_ms + 1;
+        assert_eq!(trade(&mut book, &mut ledger, &mut fees, 30, later), (-1, 5));
+        assert_eq!(ledger.balance(2, Asset::Quote).locked, 0);
+
+        // Fee bersih (5 + 2 + 5 - 3 rebate) ada di akun fee, total saldo tetap sama dengan yang dikreditkan
+        assert_eq!(ledger.available(FEE_ACCOUNT, Asset::Quote), 9);
+        let total = |asset| -> u64 {
+            [1, 2, FEE_ACCOUNT].iter().map(|&user_id| {
+                let balance = ledger.balance(user_id, asset);
+                balance.available + balance.locked
+            }).sum()
+        };
+        assert_eq!((total(Asset::Base), total(Asset::Quote)), (100, 100_000));
+
+        // Fill sebesar apa pun tidak overflow (required_funds dan settlement sama-sama di u128)
+        let mut ledger = Ledger::new();
+        ledger.credit(1, Asset::Base, u64::MAX);
+        ledger.reserve(Reservation { order_id: 1, user_id: 1, asset: Asset::Base, amount: u64::MAX });
+        let mut book = OrderBook::new();
+        let mut events = book.place_limit_order(1, 1, Side::Ask, u64::MAX, u64::MAX);
+        events.extend(book.place_limit_order(2, 2, Side::Bid, u64::MAX, u64::MAX));
+        ledger.apply_events(&events, &book, &schedule);
+        assert_eq!(ledger.balance(1, Asset::Base), Balance::default());
+    }
+
+    #[test]
+    fn test_withdrawal_cannot_touch_locked_funds() {
+        use ledger::{Asset, Balance, Ledger, Reservation};
+
+        let mut ledger = Ledger::new();
+        assert!(ledger.deposit(1, 7, Asset::Quote, 1_000));
+        let reservation = Reservation { order_id: 1, user_id: 7, asset: Asset::Quote, amount: 600 };
+        assert!(ledger.can_reserve(&reservation));
+        ledger.reserve(reservation);
+
+        // Hanya 400 yang available (processor menolak withdrawal yang lebih besar sebelum WAL)
+        assert_eq!(ledger.available(7, Asset::Quote), 400);
+        assert!(ledger.withdraw(2, 7, Asset::Quote, 400));
+        assert_eq!(ledger.balance(7, Asset::Quote), Balance { available: 0, locked: 600 });
+
+        // Journal id yang sudah diterapkan diabaikan (replay ulang tidak menggandakan saldo)
+        assert!(!ledger.deposit(1, 7, Asset::Quote, 1_000));
+        assert!(ledger.is_journaled(2));
+        assert!(!ledger.is_journaled(3));
+        assert_eq!(ledger.balance(7, Asset::Quote).available, 0);
+    }
+
+    #[test]
+    fn test_shared_balances_are_rebuilt_from_market_contributions() {
+        use ledger::{Asset, Balance, Ledger, MarketAssets, Reservation, SharedAccounts};
+
+        let accounts = SharedAccounts::new();
+        let mut sol = Ledger::new();
+        let mut btc = Ledger::new();
+        sol.attach(MarketAssets::from_symbol("SOL_USDC"), accounts.clone());
+        btc.attach(MarketAssets::from_symbol("BTC_USDC"), accounts.clone());
+
+        // Deposit USDC di SOL_USDC, dikunci oleh order di BTC_USDC
+        assert!(sol.deposit(1, 7, Asset::Quote, 1_000));
+        btc.reserve(Reservation { order_id: 1, user_id: 7, asset: Asset::Quote, amount: 600 });
+        assert_eq!(sol.balance(7, Asset::Quote), Balance { available: 400, locked: 600 });
+        assert!(btc.is_journaled(1));
+        assert_eq!(btc.balance(7, Asset::Base), Balance::default());
+
+        // Recovery: setiap market hanya membawa kontribusinya sendiri, urutan attach tidak berpengaruh
+        let restore = |ledger: &Ledger| bincode::deserialize::<Ledger>(&bincode::serialize(ledger).unwrap()).unwrap();
+        let (mut sol, mut btc) = (restore(&sol), restore(&btc));
+        let accounts = SharedAccounts::new();
+        btc.attach(MarketAssets::from_symbol("BTC_USDC"), accounts.clone());
+        assert_eq!(btc.balance(7, Asset::Quote), Balance { available: 0, locked: 600 });
+        sol.attach(MarketAssets::from_symbol("SOL_USDC"), accounts);
+        assert_eq!(btc.balance(7, Asset::Quote), Balance { available: 400, locked: 600 });
+        assert!(btc.is_journaled(1));
+    }
+
+    #[tokio::test]
+    async fn test_retried_deposit_is_credited_once() {
+        use ledger::Asset;
+        use processor::{Command, MarketProcessor};
+        use test_support::{deposit_command, market_config, TempDir};
+        use tokio::sync::{broadcast, mpsc, oneshot};
+
+        let dir = TempDir::new("funds_retry");
+        let (tx, rx) = mpsc::channel(8);
+        let (broadcast_tx, _) = broadcast::channel(16);
+        tokio::spawn(MarketProcessor::new(market_config(&dir), rx, broadcast_tx).unwrap().run());
+
+        // Retry dengan journal id yang sama dijawab tanpa event, saldo hanya bertambah sekali
+        for expected_events in [1, 0] {
+            let (command, reply) = deposit_command(42, 7, Asset::Quote, 1_000);
+            tx.send(command).await.unwrap();
+            assert_eq!(reply.await.unwrap().unwrap().len(), expected_events);
+        }
+
+        let withdraw = |journal_id, amount| {
+            let (responder, reply) = oneshot::channel();
+            (Command::Withdraw { journal_id, user_id: 7, asset: Asset::Quote, amount, responder }, reply)
+        };
+        let (command, reply) = withdraw(43, 1_000);
+        tx.send(command).await.unwrap();
+        assert!(reply.await.unwrap().is_ok());
+        let (command, reply) = withdraw(44, 1);
+        tx.send(command).await.unwrap();
+        assert_eq!(
+            reply.await.unwrap().unwrap_err(),
+            error::EngineError::WithdrawalRejected { asset: Asset::Quote, amount: 1, available: 0 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_market_buy_locks_only_the_sweep_cost() {
+        use ledger::Asset;
+        use processor::{Command, MarketProcessor};
+        use test_support::{deposit_command, market_config, place_command, TempDir};
+        use tokio::sync::{broadcast, mpsc, oneshot};
+        use wal::WalHandler;
+
+        let dir = TempDir::new("market_budget");
+        let config = market_config(&dir);
+        let (tx, rx) = mpsc::channel(8);
+        let (broadcast_tx, _) = broadcast::channel(64);
+        tokio::spawn(MarketProcessor::new(config.clone(), rx, broadcast_tx).unwrap().run());
+
+        let market_buy = |order_id, user_id, quantity| {
+            let (responder, reply) = oneshot::channel();
+            (Command::PlaceMarketOrder {
+                user_id, order_id, side: Side::Bid, quantity, stp: StpPolicy::default(), client_order_id: None, responder
+            }, reply)
+        };
+        let commands = [
+            deposit_command(1, 1, Asset::Base, 10),
+            deposit_command(2, 2, Asset::Quote, 1_000),
+            // Buku kosong: tidak ada yang dikunci, order langsung unfilled
+            market_buy(1, 2, 5),
+            place_command(2, 1, Side::Ask, 10, 3),
+            place_command(3, 1, Side::Ask, 12, 7),
+            // Menyapu 3 @ 10 + 2 @ 12 = 54, bukan seluruh saldo 1000
+            market_buy(4, 2, 5),
+        ];
+        for (command, reply) in commands {
+            tx.send(command).await.unwrap();
+            assert!(reply.await.unwrap().is_ok());
+        }
+
+        // Tanpa saldo quote sama sekali ditolak walaupun ada liquidity
+        let (command, reply) = market_buy(5, 3, 1);
+        tx.send(command).await.unwrap();
+        assert_eq!(reply.await.unwrap().unwrap_err().reason(), RejectReason::InsufficientBalance);
+        drop(tx);
+
+        let recovery = WalHandler::recover(&config.wal_dir, 0, &[]).unwrap();
+        let budgets: Vec<_> = recovery.entries.iter()
+            .filter_map(|(_, entry)| match entry {
+                LogEntry::PlaceMarket { order_id, locked_quote, .. } => Some((*order_id, *locked_quote)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(budgets, vec![(1, Some(0)), (4, Some(54))]);
+    }
+
+    #[test]
+    fn test_duplicate_order_ids_and_client_order_ids() {
+        use orders::{OrderRegistry, Submission};
+
+        let mut book = OrderBook::new();
+        let mut orders = OrderRegistry::new();
+        let place = |order_id, user_id, client_order_id, timestamp| {
+            place_entry(order_id, user_id, Side::Bid, 100, 10, Some(client_order_id), timestamp)
+        };
+
+        let entry = place(1, 7, "a", 1_000);
+        assert!(matches!(orders.check(&entry, &book), Submission::New));
+        let events = book.place_limit_order(1, 7, Side::Bid, 100, 10);
+        orders.record(&entry, &events, &book);
+        assert_eq!(orders.resolve(7, "a"), Some(1));
+        assert_eq!(orders.resolve(8, "a"), None);
+
+        // Retry identik (timestamp boleh beda) mendapat hasil asli, request berbeda dengan ID sama ditolak
+        assert!(matches!(&orders.check(&place(1, 7, "a", 2_000), &book), Submission::Retry(e) if e.len() == 1));
+        assert!(matches!(orders.check(&place(1, 8, "a", 2_000), &book), Submission::Duplicate(RejectReason::DuplicateOrderId)));
+
+        // Client order ID unik per user selama ordernya hidup
+        assert!(matches!(orders.check(&place(2, 7, "a", 2_000), &book), Submission::Duplicate(RejectReason::DuplicateClientOrderId)));
+        assert!(matches!(orders.check(&place(2, 8, "a", 2_000), &book), Submission::New));
+
+        let events = book.cancel_order(1, 7);
+        orders.record(&LogEntry::Cancel { order_id: 1, user_id: 7 }, &events, &book);
+        assert_eq!(orders.resolve(7, "a"), None);
+        assert!(matches!(orders.check(&place(2, 7, "a", 3_000), &book), Submission::New));
+
+        // Hasil yang disimpan hanya event milik order itu, bukan event maker yang ikut tersentuh
+        let mut orders = OrderRegistry::with_window(2);
+        let mut book = OrderBook::new();
+        book.place_limit_order(10, 9, Side::Ask, 100, 5);
+        let taker = place_entry(11, 7, Side::Bid, 100, 5, None, 0);
+        let events = book.place_limit_order(11, 7, Side::Bid, 100, 5);
+        orders.record(&taker, &events, &book);
+        assert!(matches!(&orders.check(&taker, &book), Submission::Retry(e)
+            if matches!(e[..], [EngineEvent::TradeExecuted { taker_id: 11, .. }])));
+
+        // Keluar dari window: retry tidak bisa dibalas lagi, tapi ditolak (tidak dieksekusi ulang)
+        for order_id in 12..=13 {
+            let entry = place_entry(order_id, 7, Side::Bid, 90, 1, None, 0);
+            let events = book.place_limit_order(order_id, 7, Side::Bid, 90, 1);
+            orders.record(&entry, &events, &book);
+        }
+        assert!(matches!(orders.check(&taker, &book), Submission::Duplicate(RejectReason::DuplicateOrderId)));
+        assert!(matches!(orders.check(&place_entry(14, 7, Side::Bid, 90, 1, None, 0), &book), Submission::New));
+    }
+
+    #[test]
+    fn test_order_status_and_open_orders_index() {
+        use orders::{OrderRegistry, OrderState};
+
+        let mut book = OrderBook::new();
+        let mut orders = OrderRegistry::new();
+        let mut place = |book: &mut OrderBook, order_id, user_id, side, price, quantity| {
+            let events = book.place_limit_order(order_id, user_id, side, price, quantity);
+            orders.record(&place_entry(order_id, user_id, side, price, quantity, None, 0), &events, book);
+        };
+
+        place(&mut book, 1, 7, Side::Ask, 100, 10);
+        place(&mut book, 2, 7, Side::Ask, 101, 10);
+        place(&mut book, 3, 8, Side::Bid, 100, 4);
+
+        let status = orders.status(1, &book).unwrap();
+        assert_eq!((status.state, status.filled_quantity, status.remaining_quantity), (OrderState::Open, 4, 6));
+        let open: Vec<OrderId> = orders.open_orders(7, &book).iter().map(|s| s.order_id).collect();
+        assert_eq!(open, vec![1, 2]);
+
+        // Taker yang terisi penuh langsung selesai, tidak masuk index
+        let status = orders.status(3, &book).unwrap();
+        assert_eq!((status.state, status.remaining_quantity), (OrderState::Filled, 0));
+        assert!(orders.open_orders(8, &book).is_empty());
+    }
+}
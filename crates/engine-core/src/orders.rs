@@ -0,0 +1,349 @@
+// crates/engine-core/src/orders.rs
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use serde::{Serialize, Deserialize};
+use crate::{OrderBook, OrderId, UserId, Price, Quantity, Side, EngineEvent, LogEntry, RejectReason};
+use crate::journal::input_checksum;
+use crate::ledger::touched_orders;
+
+// Jumlah hasil placement terakhir yang disimpan untuk retry idempotent dan query status.
+// Order ID yang lebih tua dari ini tetap tercatat sebagai sudah dipakai (retry-nya ditolak)
+const IDEMPOTENCY_WINDOW: usize = 100_000;
+
+// Hasil pengecekan order ID sebelum placement
+#[derive(Debug)]
+pub enum Submission {
+    // Order ID baru, lanjut ke validasi dan WAL
+    New,
+    // Retry dari request yang identik: hasil aslinya dikembalikan tanpa eksekusi ulang
+    Retry(Vec<EngineEvent>),
+    Duplicate(RejectReason),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderState {
+    // Resting di buku
+    Open,
+    // Stop order yang belum ter-trigger
+    PendingTrigger,
+    Filled,
+    // Keluar dari buku sebelum terisi penuh (cancel, STP, expired, IOC/FOK/market yang tidak terisi, post-only reject)
+    Cancelled,
+}
+
+// Snapshot status satu order untuk query (read-only)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderStatus {
+    pub order_id: OrderId,
+    pub user_id: UserId,
+    pub side: Side,
+    // None = market / Stop-Market
+    pub price: Option<Price>,
+    // Hanya untuk stop yang belum ter-trigger
+    pub trigger_price: Option<Price>,
+    pub filled_quantity: Quantity,
+    // Sisa di buku termasuk reserve iceberg (0 jika order sudah selesai)
+    pub remaining_quantity: Quantity,
+    pub client_order_id: Option<String>,
+    pub state: OrderState,
+}
+
+// Data order yang tidak tersimpan di buku: identitas, total fill, dan status akhir setelah keluar dari buku
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OrderRecord {
+    user_id: UserId,
+    side: Side,
+    price: Option<Price>,
+    client_order_id: Option<String>,
+    filled: Quantity,
+    state: OrderState,
+}
+
+// Hasil placement yang cukup untuk membalas retry: checksum request (tanpa timestamp)
+// dan hanya event milik order ini (event order lain yang ikut ter-trigger tidak disimpan)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PlacementResult {
+    request_crc: u32,
+    events: Vec<EngineEvent>,
+}
+
+// Himpunan order ID dalam bentuk rentang [awal, akhir], ringkas untuk ID yang berurutan
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IdRanges(BTreeMap<OrderId, OrderId>);
+
+impl IdRanges {
+    fn contains(&self, id: OrderId) -> bool {
+        self.0.range(..=id).next_back().is_some_and(|(_, &end)| end >= id)
+    }
+
+    fn insert(&mut self, id: OrderId) {
+        if self.contains(id) {
+            return;
+        }
+        let (mut start, mut end) = (id, id);
+        if let Some((&previous_start, _)) = self.0.range(..id).next_back().filter(|(_, &previous_end)| previous_end + 1 == id) {
+            start = previous_start;
+        }
+        if let Some(next_end) = id.checked_add(1).and_then(|next| self.0.remove(&next)) {
+            end = next_end;
+        }
+        self.0.insert(start, end);
+    }
+}
+
+// Identitas order: hasil placement per order ID (idempotency), client order ID per user,
+// dan index order hidup per user. Hanya diubah lewat apply(), jadi replay WAL membangun ulang state yang sama
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderRegistry {
+    placements: HashMap<OrderId, PlacementResult>,
+    // Urutan masuk `placements`, yang tertua dibuang setelah melewati window
+    history: VecDeque<OrderId>,
+    // Order ID yang sudah keluar dari window: tidak bisa dibalas lagi, tapi juga tidak boleh dieksekusi ulang
+    retired: IdRanges,
+    window: usize,
+    // Semua order hidup + order selesai yang masih dalam window
+    records: HashMap<OrderId, OrderRecord>,
+    // (User, client order ID) -> order ID, hanya untuk order yang masih hidup
+    client_ids: HashMap<(UserId, String), OrderId>,
+    // User -> order hidup (buku + Trigger Store), urut per order ID
+    open_orders: HashMap<UserId, BTreeSet<OrderId>>,
+}
+
+impl Default for OrderRegistry {
+    fn default() -> Self {
+        Self::with_window(IDEMPOTENCY_WINDOW)
+    }
+}
+
+impl OrderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registry dengan jumlah hasil placement yang disimpan selain IDEMPOTENCY_WINDOW
+    pub fn with_window(window: usize) -> Self {
+        Self {
+            placements: HashMap::new(),
+            history: VecDeque::new(),
+            retired: IdRanges::default(),
+            window,
+            records: HashMap::new(),
+            client_ids: HashMap::new(),
+            open_orders: HashMap::new(),
+        }
+    }
+
+    // Cek entry placement terhadap order ID dan client order ID yang sudah dipakai.
+    // Entry selain placement selalu New
+    pub fn check(&self, entry: &LogEntry, book: &OrderBook) -> Submission {
+        let Some(placement) = entry.placement() else {
+            return Submission::New;
+        };
+
+        if let Some(result) = self.placements.get(&placement.order_id) {
+            return match result.request_crc == input_checksum(&normalized(entry)) {
+                true => Submission::Retry(result.events.clone()),
+                false => Submission::Duplicate(RejectReason::DuplicateOrderId),
+            };
+        }
+        if self.retired.contains(placement.order_id) || book.is_live(placement.order_id) {
+            return Submission::Duplicate(RejectReason::DuplicateOrderId);
+        }
+        if placement.client_order_id.is_some_and(|id| self.resolve(placement.user_id, id).is_some()) {
+            return Submission::Duplicate(RejectReason::DuplicateClientOrderId);
+        }
+
+        Submission::New
+    }
+
+    // Order hidup milik user dengan client order ID ini
+    pub fn resolve(&self, user_id: UserId, client_order_id: &str) -> Option<OrderId> {
+        self.client_ids.get(&(user_id, client_order_id.to_string())).copied()
+    }
+
+    pub fn client_order_id(&self, order_id: OrderId) -> Option<&str> {
+        self.records.get(&order_id)?.client_order_id.as_deref()
+    }
+
+    // Status order: yang hidup dibaca dari buku, yang sudah selesai dari record (selama masih dalam window)
+    pub fn status(&self, order_id: OrderId, book: &OrderBook) -> Option<OrderStatus> {
+        let record = self.records.get(&order_id)?;
+
+        let (state, price, trigger_price, remaining_quantity) = if let Some(order) = book.order(order_id) {
+            (OrderState::Open, Some(order.price), None, order.total_quantity())
+        } else if let Some(stop) = book.stop(order_id) {
+            (OrderState::PendingTrigger, stop.limit_price, Some(stop.trigger_price), stop.quantity)
+        } else {
+            (record.state, record.price, None, 0)
+        };
+
+        Some(OrderStatus {
+            order_id,
+            user_id: record.user_id,
+            side: record.side,
+            price,
+            trigger_price,
+            filled_quantity: record.filled,
+            remaining_quantity,
+            client_order_id: record.client_order_id.clone(),
+            state,
+        })
+    }
+
+    // Semua order hidup milik user, urut per order ID
+    pub fn open_orders(&self, user_id: UserId, book: &OrderBook) -> Vec<OrderStatus> {
+        self.open_orders
+            .get(&user_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|&order_id| self.status(order_id, book))
+            .collect()
+    }
+
+    // Catat hasil eksekusi entry: simpan hasil placement, daftarkan order yang masih hidup,
+    // akumulasi fill, dan tutup order yang sudah keluar dari buku
+    pub fn record(&mut self, entry: &LogEntry, events: &[EngineEvent], book: &OrderBook) {
+        let placed = entry.placement();
+
+        if let Some(placement) = placed {
+            let order_id = placement.order_id;
+            self.records.insert(order_id, OrderRecord {
+                user_id: placement.user_id,
+                side: placement.side,
+                price: placement.price,
+                client_order_id: placement.client_order_id.map(String::from),
+                filled: 0,
+                state: OrderState::Open,
+            });
+
+            if book.is_live(order_id) {
+                self.open_orders.entry(placement.user_id).or_default().insert(order_id);
+                if let Some(client_order_id) = placement.client_order_id {
+                    self.client_ids.insert((placement.user_id, client_order_id.to_string()), order_id);
+                }
+            }
+            let events = events.iter().filter(|event| concerns(event, order_id)).cloned().collect();
+            self.remember(order_id, PlacementResult { request_crc: input_checksum(&normalized(entry)), events }, book);
+        }
+
+        for event in events {
+            match *event {
+                EngineEvent::TradeExecuted { maker_id, taker_id, quantity, .. } => {
+                    for order_id in [maker_id, taker_id] {
+                        if let Some(record) = self.records.get_mut(&order_id) {
+                            record.filled += quantity;
+                        }
+                    }
+                }
+                EngineEvent::OrderAmended { id, price, .. } => {
+                    if let Some(record) = self.records.get_mut(&id) {
+                        record.price = Some(price);
+                    }
+                }
+                _ => {
+                    if let Some(record) = cancelled_order(event).and_then(|id| self.records.get_mut(&id)) {
+                        record.state = OrderState::Cancelled;
+                    }
+                }
+            }
+        }
+
+        let touched = events.iter().flat_map(touched_orders).flatten();
+        for order_id in touched.chain(placed.map(|p| p.order_id)) {
+            if !book.is_live(order_id) {
+                self.close(order_id);
+            }
+        }
+    }
+
+    // Order keluar dari buku: client order ID bebas dipakai lagi, record disimpan selama masih dalam window
+    fn close(&mut self, order_id: OrderId) {
+        let Some(record) = self.records.get_mut(&order_id) else {
+            return;
+        };
+        if record.state == OrderState::Open {
+            record.state = OrderState::Filled;
+        }
+
+        let user_id = record.user_id;
+        if let Some(client_order_id) = record.client_order_id.clone() {
+            if self.client_ids.get(&(user_id, client_order_id.clone())) == Some(&order_id) {
+                self.client_ids.remove(&(user_id, client_order_id));
+            }
+        }
+        if let Some(orders) = self.open_orders.get_mut(&user_id) {
+            orders.remove(&order_id);
+            if orders.is_empty() {
+                self.open_orders.remove(&user_id);
+            }
+        }
+        if !self.placements.contains_key(&order_id) {
+            self.records.remove(&order_id);
+        }
+    }
+
+    fn remember(&mut self, order_id: OrderId, result: PlacementResult, book: &OrderBook) {
+        if self.placements.insert(order_id, result).is_none() {
+            self.history.push_back(order_id);
+        }
+        if self.history.len() > self.window {
+            if let Some(oldest) = self.history.pop_front() {
+                self.placements.remove(&oldest);
+                self.retired.insert(oldest);
+                // Record order yang masih hidup dipertahankan sampai ordernya selesai
+                if !book.is_live(oldest) {
+                    self.records.remove(&oldest);
+                }
+            }
+        }
+    }
+}
+
+// Order yang keluar dari buku tanpa terisi penuh karena event ini
+fn cancelled_order(event: &EngineEvent) -> Option<OrderId> {
+    match *event {
+        EngineEvent::OrderCancelled { id, .. }
+        | EngineEvent::StopCancelled { id, .. }
+        | EngineEvent::OrderUnfilled { id, .. }
+        | EngineEvent::OrderKilled { id, .. }
+        | EngineEvent::OrderExpired { id, .. }
+        | EngineEvent::PostOnlyRejected { id, .. } => Some(id),
+        _ => None,
+    }
+}
+
+// Event yang dibutuhkan balasan placement order ini
+fn concerns(event: &EngineEvent, order_id: OrderId) -> bool {
+    match *event {
+        EngineEvent::TradeExecuted { maker_id, taker_id, .. }
+        | EngineEvent::SelfTradeDecremented { maker_id, taker_id, .. } => maker_id == order_id || taker_id == order_id,
+        EngineEvent::OrderPlaced { id, .. }
+        | EngineEvent::OrderCancelled { id, .. }
+        | EngineEvent::OrderUnfilled { id, .. }
+        | EngineEvent::OrderKilled { id, .. }
+        | EngineEvent::OrderExpired { id, .. }
+        | EngineEvent::OrderReplenished { id, .. }
+        | EngineEvent::OrderAmended { id, .. }
+        | EngineEvent::StopPlaced { id, .. }
+        | EngineEvent::StopTriggered { id, .. }
+        | EngineEvent::StopCancelled { id, .. }
+        | EngineEvent::PostOnlyRejected { id, .. }
+        | EngineEvent::PostOnlySlid { id, .. }
+        | EngineEvent::OrderRejected { id, .. } => id == order_id,
+        _ => false,
+    }
+}
+
+// Request yang sama dikirim ulang dengan timestamp (dan saldo) berbeda, jadi keduanya tidak ikut dibandingkan
+fn normalized(entry: &LogEntry) -> LogEntry {
+    let mut entry = entry.clone();
+    if let LogEntry::Place { timestamp, .. }
+    | LogEntry::PlaceMarket { timestamp, .. }
+    | LogEntry::PlaceStop { timestamp, .. } = &mut entry {
+        *timestamp = 0;
+    }
+    if let LogEntry::PlaceMarket { locked_quote, .. } = &mut entry {
+        *locked_quote = None;
+    }
+    entry
+}
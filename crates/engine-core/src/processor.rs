@@ -1,129 +1,967 @@
 // crates/engine-core/src/processor.rs
 
+use std::io;
+use std::path::Path;
+use std::time::Duration;
 use tokio::sync::{mpsc, broadcast};
-use crate::{OrderBook, Side, EngineEvent, OrderLevel, LogEntry};
-use crate::wal::WalHandler; 
+use crate::clock::{Clock, SystemClock};
+use crate::error::EngineError;
+use crate::fees::{FeeEngine, FeeSchedule};
+use crate::instrument::InstrumentSpec;
+use crate::journal::EventJournal;
+use crate::ledger::{self, Asset, Ledger, MarketAssets, Reservation, SharedAccounts};
+use crate::orders::{OrderRegistry, OrderStatus, Submission};
+use crate::{OrderBook, Side, EngineEvent, OrderLevel, LogEntry, OrderOptions, TimeInForce, PostOnly, StpPolicy, RejectReason, CancelFilter};
+use crate::snapshot::SnapshotStore;
+use crate::stops::StopOrder;
+use crate::wal::{Durability, Migration, SegmentPolicy, SegmentWriter, WalError, WalHandler};
+
+// Channel balasan command yang mengubah state: event hasil eksekusi, atau alasan penolakan
+pub type Responder = tokio::sync::oneshot::Sender<Result<Vec<EngineEvent>, EngineError>>;
 
 #[derive(Debug)]
 pub enum Command {
     PlaceOrder {
         user_id: u64,
-        order_id: u64, 
+        order_id: u64,
         side: Side,
         price: u64,
         quantity: u64,
+        time_in_force: TimeInForce,
+        post_only: PostOnly,
+        stp: StpPolicy,
+        display_quantity: Option<u64>,
+        // ID dari client, unik per user di antara order yang masih hidup
+        client_order_id: Option<String>,
         // Channel untuk mengirim balik hasil ke API handler (One-shot)
-        responder: tokio::sync::oneshot::Sender<Vec<EngineEvent>>, 
+        responder: Responder,
+    },
+    PlaceMarketOrder {
+        user_id: u64,
+        order_id: u64,
+        side: Side,
+        quantity: u64,
+        stp: StpPolicy,
+        client_order_id: Option<String>,
+        responder: Responder,
     },
     CancelOrder {
         user_id: u64,
         order_id: u64,
-        responder: tokio::sync::oneshot::Sender<Vec<EngineEvent>>,
+        // Jika diisi, order dicari lewat client order ID (order_id diabaikan)
+        client_order_id: Option<String>,
+        responder: Responder,
+    },
+    // Kill switch: cancel semua order user (opsional per side / rentang harga)
+    MassCancel {
+        user_id: u64,
+        filter: CancelFilter,
+        responder: Responder,
+    },
+    AmendOrder {
+        user_id: u64,
+        order_id: u64,
+        price: u64,
+        quantity: u64,
+        responder: Responder,
+    },
+    PlaceStopOrder {
+        user_id: u64,
+        order_id: u64,
+        side: Side,
+        trigger_price: u64,
+        // None = Stop-Market
+        limit_price: Option<u64>,
+        quantity: u64,
+        stp: StpPolicy,
+        client_order_id: Option<String>,
+        responder: Responder,
+    },
+    // Mutasi saldo dari settlement layer eksternal. journal_id dari pemanggil,
+    // retry dengan journal_id yang sudah diterapkan dijawab tanpa event (saldo tidak berubah dua kali)
+    Deposit {
+        journal_id: u64,
+        user_id: u64,
+        asset: Asset,
+        amount: u64,
+        responder: Responder,
+    },
+    Withdraw {
+        journal_id: u64,
+        user_id: u64,
+        asset: Asset,
+        amount: u64,
+        responder: Responder,
+    },
+    // Query read-only (tidak ditulis ke WAL). Order milik user lain dilaporkan sebagai tidak ditemukan
+    GetOrder {
+        user_id: u64,
+        order_id: u64,
+        // Jika diisi, order hidup dicari lewat client order ID (order_id diabaikan)
+        client_order_id: Option<String>,
+        responder: tokio::sync::oneshot::Sender<Result<OrderStatus, EngineError>>,
+    },
+    ListOpenOrders {
+        user_id: u64,
+        responder: tokio::sync::oneshot::Sender<Vec<OrderStatus>>,
     },
     GetDepth {
         limit: usize,
         // Responder mengembalikan tuple (Asks, Bids)
         responder: tokio::sync::oneshot::Sender<(Vec<OrderLevel>, Vec<OrderLevel>)>,
+    },
+    // Tulis snapshot state market sekarang, responder mendapat seq WAL yang tercakup snapshot
+    Snapshot {
+        responder: tokio::sync::oneshot::Sender<io::Result<u64>>,
+    },
+    // Operator: buka kembali market yang berhenti karena WAL gagal ditulis
+    Resume {
+        responder: tokio::sync::oneshot::Sender<io::Result<()>>,
+    },
+}
+
+// Seberapa sering processor mengecek GTD order yang kedaluwarsa
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_millis(100);
+
+// Jumlah entry WAL antar snapshot otomatis: membatasi panjang replay saat startup
+const DEFAULT_SNAPSHOT_INTERVAL: u64 = 10_000;
+
+// Konfigurasi satu market (instrument): setiap market punya OrderBook dan WAL sendiri
+#[derive(Debug, Clone)]
+pub struct MarketConfig {
+    pub symbol: String,
+    // Direktori segment WAL + MANIFEST
+    pub wal_dir: String,
+    pub wal_segments: SegmentPolicy,
+    pub durability: Durability,
+    // Segment yang sudah tercakup snapshot dipindah ke sini (None = dihapus)
+    pub wal_archive_dir: Option<String>,
+    // Migrasi untuk segment WAL dari matching version lama (tanpa migrasi, recovery menolak segment tsb)
+    pub wal_migrations: Vec<Migration>,
+    pub snapshot_dir: String,
+    // Journal event output (trade, cancel, dll) per entry WAL untuk konsumen downstream
+    pub journal_path: String,
+    // Snapshot otomatis setiap N entry WAL (0 = hanya lewat Command::Snapshot)
+    pub snapshot_interval: u64,
+    pub spec: InstrumentSpec,
+    // Symbol aset base/quote, key saldo yang dipakai bersama market lain dengan aset yang sama
+    pub assets: MarketAssets,
+    pub fees: FeeSchedule,
+}
+
+impl MarketConfig {
+    // Default WAL di velocity-<SYMBOL>-wal/ (segment dihapus setelah tercakup snapshot),
+    // snapshot di velocity-<SYMBOL>-snapshots/ setiap 10k entry, journal event di velocity-<SYMBOL>-events.journal,
+    // spec default (tanpa batasan tick/lot), aset dari symbol "<BASE>_<QUOTE>", tanpa fee
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            wal_dir: format!("velocity-{}-wal", symbol),
+            wal_segments: SegmentPolicy::default(),
+            durability: Durability::default(),
+            wal_archive_dir: None,
+            wal_migrations: Vec::new(),
+            snapshot_dir: format!("velocity-{}-snapshots", symbol),
+            journal_path: format!("velocity-{}-events.journal", symbol),
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            spec: InstrumentSpec::default(),
+            assets: MarketAssets::from_symbol(symbol),
+            fees: FeeSchedule::default(),
+        }
+    }
+
+    pub fn with_assets(mut self, base: &str, quote: &str) -> Self {
+        assert!(base != quote, "base and quote must be different assets");
+        self.assets = MarketAssets::new(base, quote);
+        self
+    }
+
+    pub fn with_spec(mut self, spec: InstrumentSpec) -> Self {
+        assert!(spec.tick_size > 0 && spec.lot_size > 0, "tick_size and lot_size must be non-zero");
+        self.spec = spec;
+        self
+    }
+
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        assert!(fees.is_valid(), "invalid fee schedule");
+        self.fees = fees;
+        self
     }
+
+    pub fn with_wal_segments(mut self, policy: SegmentPolicy) -> Self {
+        assert!(policy.max_bytes > 0 && policy.max_entries > 0, "segment thresholds must be non-zero");
+        self.wal_segments = policy;
+        self
+    }
+
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        if let Durability::GroupCommit { max_entries, .. } = durability {
+            assert!(max_entries > 0, "group commit batch must hold at least one entry");
+        }
+        self.durability = durability;
+        self
+    }
+
+    // Simpan segment lama di `dir` (untuk audit/backup) alih-alih menghapusnya
+    pub fn with_wal_archive(mut self, dir: &str) -> Self {
+        self.wal_archive_dir = Some(dir.to_string());
+        self
+    }
+
+    pub fn with_wal_migration(mut self, migration: Migration) -> Self {
+        assert!(
+            migration.from_version != crate::MATCHING_VERSION,
+            "migration source must differ from the current matching version"
+        );
+        self.wal_migrations.retain(|existing| existing.from_version != migration.from_version);
+        self.wal_migrations.push(migration);
+        self
+    }
+
+    pub fn with_snapshot_interval(mut self, entries: u64) -> Self {
+        self.snapshot_interval = entries;
+        self
+    }
+}
+
+// Event engine yang sudah ditandai symbol market asalnya (untuk broadcast lintas market)
+#[derive(Debug, Clone)]
+pub struct MarketEvent {
+    pub symbol: String,
+    pub event: EngineEvent,
+}
+
+// Hasil eksekusi yang belum boleh keluar karena entry WAL-nya belum durable (group commit)
+#[derive(Default)]
+struct Outbox {
+    events: Vec<MarketEvent>,
+    responses: Vec<(Responder, Result<Vec<EngineEvent>, EngineError>)>,
+    // Batas waktu batch yang sedang terbuka (max_delay sejak entry pertama yang belum di-sync)
+    deadline: Option<tokio::time::Instant>,
 }
 
 pub struct MarketProcessor {
-    book: OrderBook, 
+    symbol: String,
+    // Reference data untuk validasi order sebelum ditulis ke WAL
+    spec: InstrumentSpec,
+    book: OrderBook,
+    // Saldo user (dibagi dengan market lain lewat SharedAccounts), selalu berubah bersama OrderBook lewat apply()
+    ledger: Ledger,
+    // Fee schedule + rolling volume per user
+    fees: FeeEngine,
+    // Order ID untuk idempotency + client order ID per user
+    orders: OrderRegistry,
     receiver: mpsc::Receiver<Command>,
     wal: WalHandler,
-    pub event_broadcaster: broadcast::Sender<EngineEvent>,
+    wal_archive_dir: Option<String>,
+    // Event output setiap entry WAL, ditulis setelah entry-nya durable
+    journal: EventJournal,
+    // Group commit: event dan balasan yang ditahan sampai batch WAL-nya durable
+    outbox: Outbox,
+    // WAL gagal ditulis: read-only sampai Command::Resume
+    halted: bool,
+    snapshots: SnapshotStore,
+    snapshot_interval: u64,
+    // Seq WAL yang tercakup snapshot terakhir
+    snapshot_seq: u64,
+    // Sumber timestamp untuk setiap entry WAL (injectable untuk test)
+    clock: Box<dyn Clock>,
+    pub event_broadcaster: broadcast::Sender<MarketEvent>,
 }
 
 impl MarketProcessor {
-    pub fn new(receiver: mpsc::Receiver<Command>, broadcaster: broadcast::Sender<EngineEvent>) -> Self {
-        let wal_path = "velocity.wal";
-        
-        // 1. Recovery Phase
-        println!("Recovering state from WAL...");
-        let mut book = OrderBook::new();
-        
-        // Load log lama jika ada
-        if let Ok(entries) = WalHandler::read_all(wal_path) {
-            println!("Replaying {} events...", entries.len());
-            for entry in entries {
-                match entry {
-                    LogEntry::Place { order_id, user_id, side, price, quantity } => {
-                        book.place_limit_order(order_id, user_id, side, price, quantity);
-                    }
-                    LogEntry::Cancel { order_id, user_id } => {
-                        book.cancel_order(order_id, user_id);
-                    }
+    pub fn new(
+        config: MarketConfig,
+        receiver: mpsc::Receiver<Command>,
+        broadcaster: broadcast::Sender<MarketEvent>
+    ) -> Result<Self, WalError> {
+        Self::with_clock(config, receiver, broadcaster, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(
+        config: MarketConfig,
+        receiver: mpsc::Receiver<Command>,
+        broadcaster: broadcast::Sender<MarketEvent>,
+        clock: Box<dyn Clock>
+    ) -> Result<Self, WalError> {
+        // 1. Recovery Phase: mulai dari snapshot valid terbaru, lalu replay sisa WAL sesudahnya
+        println!("[{}] Recovering state from snapshot + WAL...", config.symbol);
+        let snapshots = SnapshotStore::new(&config.snapshot_dir);
+        let (snapshot_seq, snapshot_event_seq, mut book, mut ledger, mut fees, mut orders) =
+            match snapshots.load_latest().map_err(WalError::Snapshot)? {
+                Some(snapshot) => {
+                    println!("[{}] Loaded snapshot at seq {}", config.symbol, snapshot.seq);
+                    let mut fees = snapshot.fees;
+                    fees.set_schedule(config.fees);
+                    (snapshot.seq, snapshot.next_event_seq, snapshot.book, snapshot.ledger, fees, snapshot.orders)
                 }
+                None => (
+                    0,
+                    1,
+                    OrderBook::with_tick_size(config.spec.tick_size),
+                    Ledger::new(),
+                    FeeEngine::new(config.fees),
+                    OrderRegistry::new(),
+                ),
+            };
+
+        // Sampai di-attach ke registry, saldo hanya berisi kontribusi market ini
+        ledger.attach(config.assets.clone(), SharedAccounts::new());
+
+        // Load log lama jika ada. Korupsi di tengah log menggagalkan startup (bukan diam-diam dipotong)
+        let mut recovery = WalHandler::recover(&config.wal_dir, snapshot_seq, &config.wal_migrations)?;
+        if let Some(active) = recovery.active.as_ref().filter(|_| recovery.torn_bytes > 0) {
+            eprintln!(
+                "[{}] WARNING: discarding {} bytes of incomplete WAL record at {} offset {}",
+                config.symbol, recovery.torn_bytes, active.path.display(), active.len
+            );
+        }
+
+        let suffix: Vec<&(u64, LogEntry)> = recovery.entries.iter().filter(|(seq, _)| *seq > snapshot_seq).collect();
+        if let Some(&&(found, _)) = suffix.first() {
+            if found != snapshot_seq + 1 {
+                return Err(WalError::MissingEntries { expected: snapshot_seq + 1, found });
             }
-        } else {
-            println!("No WAL found, starting fresh.");
         }
+        // Seq baru selalu melanjutkan snapshot, walaupun log lebih pendek dari snapshot
+        recovery.last_seq = recovery.last_seq.max(snapshot_seq);
 
-        // 2. Open WAL for Writing
-        let wal = WalHandler::new(wal_path).expect("Failed to open WAL file");
+        // Journal event: record untuk entry yang tidak durable dibuang, record yang hilang dibangun ulang saat replay.
+        // Event sebelum snapshot tidak bisa dibangun ulang, jadi journal tidak boleh berhenti sebelum snapshot
+        let journal_recovery = EventJournal::recover(&config.journal_path, snapshot_seq, recovery.last_seq)?;
+        if journal_recovery.discarded > 0 || journal_recovery.torn_bytes > 0 {
+            eprintln!(
+                "[{}] WARNING: discarding {} event journal records beyond the WAL and {} bytes of incomplete record",
+                config.symbol, journal_recovery.discarded, journal_recovery.torn_bytes
+            );
+        }
+        // Journal yang hilang/kosong tidak boleh dimulai ulang dari event seq 1: konsumen sudah melihat seq tersebut
+        if journal_recovery.last_input_seq < snapshot_seq {
+            return Err(WalError::JournalMismatch {
+                input_seq: journal_recovery.last_input_seq + 1,
+                detail: format!("journal ends before snapshot seq {}, events cannot be regenerated", snapshot_seq),
+            });
+        }
+        let journal_event_seq = journal_recovery
+            .records
+            .first()
+            .map_or(journal_recovery.next_event_seq, |record| record.first_event_seq);
+        if journal_event_seq != snapshot_event_seq {
+            return Err(WalError::JournalMismatch {
+                input_seq: snapshot_seq + 1,
+                detail: format!(
+                    "journal continues at event seq {} but snapshot seq {} expects {}",
+                    journal_event_seq, snapshot_seq, snapshot_event_seq
+                ),
+            });
+        }
+        let mut journal = EventJournal::open(&config.journal_path, &journal_recovery)?;
 
-        Self {
+        // Replay sekaligus verifikasi: event hasil replay harus identik dengan yang sudah ada di journal
+        println!("[{}] Replaying {} events...", config.symbol, suffix.len());
+        let mut journaled = journal_recovery.records.iter();
+        for (seq, entry) in suffix {
+            let events = Self::apply(&mut book, &mut ledger, &mut fees, &mut orders, entry);
+            // Stop yang tercatat ter-trigger harus sudah keluar dari Trigger Store, selain itu logika replay berbeda
+            if let LogEntry::StopTriggered { order_id } = *entry {
+                if book.has_stop(order_id) {
+                    return Err(WalError::ReplayDivergence {
+                        input_seq: *seq,
+                        detail: format!("stop {} was triggered in the log but is still pending after replay", order_id),
+                    });
+                }
+            }
+            if *seq > journal_recovery.last_input_seq {
+                journal.append(*seq, entry, &events)?;
+            } else if !journaled.next().is_some_and(|record| record.input_seq == *seq && record.matches(entry, &events)) {
+                return Err(WalError::JournalMismatch {
+                    input_seq: *seq,
+                    detail: "replayed events differ from the journal".to_string(),
+                });
+            }
+        }
+        journal.sync()?;
+
+        // 2. Open WAL for Writing (tail yang terpotong dibuang)
+        let wal = WalHandler::open(&config.wal_dir, &recovery, config.wal_segments, config.durability)?;
+
+        Ok(Self {
+            symbol: config.symbol,
+            spec: config.spec,
             book,
+            ledger,
+            fees,
+            orders,
             receiver,
             wal,
+            wal_archive_dir: config.wal_archive_dir,
+            journal,
+            outbox: Outbox::default(),
+            halted: false,
+            snapshots,
+            snapshot_interval: config.snapshot_interval,
+            snapshot_seq,
+            clock,
             event_broadcaster: broadcaster,
+        })
+    }
+
+    // Pakai saldo bersama market lain. Kontribusi market ini (hasil recovery) ditambahkan ke `accounts`
+    pub fn with_accounts(mut self, accounts: SharedAccounts) -> Self {
+        let assets = self.ledger.assets().clone();
+        self.ledger.attach(assets, accounts);
+        self
+    }
+
+    // Ganti writer segment WAL aktif (injectable untuk test, e.g. disk yang gagal)
+    pub fn with_wal_writer(mut self, writer: Box<dyn SegmentWriter>) -> Self {
+        self.wal.replace_writer(writer);
+        self
+    }
+
+    // Satu-satunya jalur eksekusi entry WAL ke OrderBook.
+    // Dipakai saat live dan saat replay agar hasilnya identik (deterministic)
+    fn apply(
+        book: &mut OrderBook,
+        ledger: &mut Ledger,
+        fees: &mut FeeEngine,
+        orders: &mut OrderRegistry,
+        entry: &LogEntry
+    ) -> Vec<EngineEvent> {
+        // Waktu engine selalu diambil dari log, bukan dari jam dinding
+        if let Some(timestamp) = entry.timestamp() {
+            book.advance_time(timestamp);
+        }
+
+        // Order ID ganda sudah ditolak sebelum WAL, ini hanya pengaman agar buku tidak pernah tertimpa
+        if let Some(placement) = entry.placement() {
+            let reason = match orders.check(entry, book) {
+                Submission::New => None,
+                Submission::Retry(_) => Some(RejectReason::DuplicateOrderId),
+                Submission::Duplicate(reason) => Some(reason),
+            };
+            if let Some(reason) = reason {
+                return vec![EngineEvent::OrderRejected { id: placement.order_id, user_id: placement.user_id, reason }];
+            }
+        }
+
+        // Kunci dana sebelum order menyentuh buku. Saldo sudah dicek sebelum WAL (bisa jadi dengan saldo
+        // dari market lain), jadi di sini tidak dicek ulang agar replay tidak bergantung pada market lain
+        let reservation = Self::reservation(book, fees.schedule(), entry);
+        if let Some(reservation) = reservation {
+            ledger.reserve(reservation);
+        }
+        // Market buy / Stop-Market buy tidak boleh membelanjakan lebih dari dana yang dikunci
+        let budget = reservation
+            .filter(|r| r.asset == Asset::Quote)
+            .map(|r| fees.schedule().notional_budget(r.amount));
+
+        let mut events = match *entry {
+            LogEntry::Place { order_id, user_id, side, price, quantity, time_in_force, post_only, stp, display_quantity, .. } => {
+                let options = OrderOptions { time_in_force, post_only, stp, display_quantity };
+                book.place_order(order_id, user_id, side, price, quantity, options)
+            }
+            LogEntry::Cancel { order_id, user_id } => {
+                book.cancel_order(order_id, user_id)
+            }
+            LogEntry::CancelAll { user_id, filter } => {
+                book.cancel_all(user_id, filter)
+            }
+            LogEntry::PlaceMarket { order_id, user_id, side, quantity, stp, .. } => {
+                book.place_market_order_with_budget(order_id, user_id, side, quantity, stp, budget)
+            }
+            LogEntry::Amend { order_id, user_id, price, quantity, .. } => {
+                book.amend_order(order_id, user_id, price, quantity)
+            }
+            LogEntry::PlaceStop { order_id, user_id, side, trigger_price, limit_price, quantity, stp, .. } => {
+                book.place_stop_order(StopOrder {
+                    id: order_id, user_id, side, trigger_price, limit_price, quantity, stp,
+                    budget: budget.filter(|_| limit_price.is_none()),
+                })
+            }
+            LogEntry::StopTriggered { .. } => {
+                // Penanda audit: trigger sudah terjadi saat entry sebelumnya di-replay (diverifikasi saat recovery)
+                Vec::new()
+            }
+            LogEntry::Expire { timestamp } => {
+                book.expire_orders(timestamp)
+            }
+            LogEntry::Deposit { journal_id, user_id, asset, amount, .. } => {
+                // Journal id yang sudah diterapkan dilewati tanpa event
+                match ledger.deposit(journal_id, user_id, asset, amount) {
+                    true => vec![EngineEvent::FundsDeposited { journal_id, user_id, asset, amount }],
+                    false => Vec::new(),
+                }
+            }
+            LogEntry::Withdraw { journal_id, user_id, asset, amount, .. } => {
+                match ledger.withdraw(journal_id, user_id, asset, amount) {
+                    true => vec![EngineEvent::FundsWithdrawn { journal_id, user_id, asset, amount }],
+                    false => Vec::new(),
+                }
+            }
+        };
+
+        // Hitung fee setiap trade, lalu settle trade dan lepas dana order yang sudah selesai
+        fees.charge(&mut events, book.now());
+        ledger.apply_events(&events, book, fees.schedule());
+        orders.record(entry, &events, book);
+
+        events
+    }
+
+    // Dana yang harus terkunci untuk order pada entry ini (None = entry tidak mengunci dana).
+    // Market buy mengunci budget yang tercatat di entry (lihat market_budget), sisanya dikembalikan
+    // setelah eksekusi. Stop-Market buy mengunci trigger_price * quantity
+    fn reservation(book: &OrderBook, fees: &FeeSchedule, entry: &LogEntry) -> Option<Reservation> {
+        let (order_id, user_id, (asset, amount)) = match *entry {
+            LogEntry::Place { order_id, user_id, side, price, quantity, .. } => {
+                (order_id, user_id, ledger::required_funds(side, price, quantity, fees))
+            }
+            LogEntry::PlaceMarket { order_id, user_id, side: Side::Bid, locked_quote, .. } => {
+                (order_id, user_id, (Asset::Quote, locked_quote.unwrap_or(0)))
+            }
+            LogEntry::PlaceMarket { order_id, user_id, side: Side::Ask, quantity, .. } => {
+                (order_id, user_id, (Asset::Base, quantity))
+            }
+            LogEntry::PlaceStop { order_id, user_id, side, trigger_price, limit_price, quantity, .. } => {
+                (order_id, user_id, ledger::required_funds(side, limit_price.unwrap_or(trigger_price), quantity, fees))
+            }
+            LogEntry::Amend { order_id, user_id, price, quantity, .. } => {
+                // Amend yang akan ditolak buku (bukan pemilik, quantity 0) tidak mengubah dana
+                let order = book.order(order_id).filter(|o| o.user_id == user_id && quantity > 0)?;
+                (order_id, user_id, ledger::required_funds(order.side, price, quantity, fees))
+            }
+            _ => return None,
+        };
+
+        Some(Reservation { order_id, user_id, asset, amount })
+    }
+
+    // Quote yang dikunci market buy: biaya menyapu buku untuk `quantity` (plus fee maksimum),
+    // dibatasi quote available. Dihitung di dalam admission agar saldo tidak dipakai market lain di antaranya.
+    // Buku kosong tidak mengunci apa pun (order langsung unfilled), tanpa saldo sama sekali ditolak
+    fn market_budget(&self, user_id: u64, quantity: u64, stp: StpPolicy) -> Result<u64, RejectReason> {
+        let cost = self.fees.schedule().with_max_fee(self.book.sweep_cost(user_id, Side::Bid, quantity, stp));
+        let budget = u64::try_from(cost).unwrap_or(u64::MAX).min(self.ledger.available(user_id, Asset::Quote));
+        match budget {
+            0 if cost > 0 => Err(RejectReason::InsufficientBalance),
+            budget => Ok(budget),
+        }
+    }
+
+    // Write-Ahead: tulis ke WAL dulu, baru eksekusi di memory dan broadcast.
+    // Fail closed: entry yang gagal ditulis tidak pernah dieksekusi dan market berhenti menerima perubahan
+    fn commit(&mut self, log_entry: LogEntry) -> Result<Vec<EngineEvent>, EngineError> {
+        if self.halted {
+            return Err(EngineError::MarketHalted);
+        }
+
+        // 1. (WAL) Persistence First (Write-Ahead)
+        if let Err(e) = self.wal.write_entry(&log_entry) {
+            self.halt(&e);
+            return Err(EngineError::MarketHalted);
+        }
+
+        // 2. Memory Execution
+        let events = Self::apply(&mut self.book, &mut self.ledger, &mut self.fees, &mut self.orders, &log_entry);
+        self.journal_events(&log_entry, &events);
+
+        // Stop yang ter-trigger dicatat juga di WAL sebagai penanda audit
+        for event in &events {
+            if let EngineEvent::StopTriggered { id, .. } = event {
+                // Entry utama sudah tercatat (trigger ikut ter-replay darinya), market cukup dihentikan
+                let marker = LogEntry::StopTriggered { order_id: *id };
+                match self.wal.write_entry(&marker) {
+                    Ok(()) => self.journal_events(&marker, &[]),
+                    Err(e) => self.halt(&e),
+                }
+            }
+        }
+        if self.wal.unsynced() == 0 {
+            self.flush_journal();
+        }
+
+        // 3. Broadcast (Pub/Sub)
+        // Kirim copy event ke semua subscriber WebSocket
+        for event in &events {
+            // Hanya broadcast event publik (Trade). Private info (OrderPlaced) opsional.
+            // Di sini broadcast semuanya agar dashboard terlihat hidup
+            self.publish(event.clone());
+        }
+
+        // Group commit: batch dibuka oleh entry pertama yang belum di-sync
+        if let Durability::GroupCommit { max_delay, .. } = self.wal.durability() {
+            if self.wal.unsynced() > 0 && self.outbox.deadline.is_none() {
+                self.outbox.deadline = Some(tokio::time::Instant::now() + max_delay);
+            }
+        }
+
+        Ok(events)
+    }
+
+    // Catat event entry WAL terakhir ke journal (ditulis ke file setelah entry-nya durable)
+    fn journal_events(&mut self, entry: &LogEntry, events: &[EngineEvent]) {
+        if let Err(e) = self.journal.append(self.wal.last_seq(), entry, events) {
+            self.halt_journal(&e);
+        }
+    }
+
+    fn flush_journal(&mut self) {
+        if let Err(e) = self.journal.flush() {
+            self.halt_journal(&e);
+        }
+    }
+
+    // Journal gagal ditulis: entry WAL sudah durable (balasan tetap dikirim), tapi market berhenti
+    // agar journal tidak bolong sampai operator resume
+    fn halt_journal(&mut self, error: &io::Error) {
+        eprintln!("[{}] CRITICAL: event journal write failed, market halted (read-only) until resumed: {}", self.symbol, error);
+        self.halted = true;
+    }
+
+    // Masuk mode read-only: query tetap dilayani, semua perubahan state ditolak sampai operator resume
+    fn halt(&mut self, error: &io::Error) {
+        eprintln!("[{}] CRITICAL: WAL write failed, market halted (read-only) until resumed: {}", self.symbol, error);
+        self.halted = true;
+    }
+
+    // Resume oleh operator: perbaiki WAL (buang record yang gagal, tulis ulang batch yang tertahan), baru buka lagi
+    fn resume(&mut self) -> io::Result<()> {
+        if !self.halted {
+            return Ok(());
+        }
+        self.wal.repair()?;
+        self.journal.repair()?;
+        self.halted = false;
+        println!("[{}] WAL repaired, market resumed", self.symbol);
+        self.sync_wal();
+        Ok(())
+    }
+
+    // 4. Respond (gRPC). Selama masih ada entry yang belum durable, balasan ditahan sampai batch di-sync,
+    // termasuk retry/penolakan agar client tidak pernah melihat hasil yang bisa hilang saat crash
+    fn respond(&mut self, responder: Responder, result: Result<Vec<EngineEvent>, EngineError>) {
+        match self.wal.unsynced() {
+            0 => respond(responder, result),
+            _ => self.outbox.responses.push((responder, result)),
+        }
+    }
+
+    // Tutup batch group commit: fsync, lalu lepas event dan balasan yang ditahan.
+    // Jika fsync gagal, batch tetap ditahan (belum durable) sampai resume berhasil menuliskannya
+    fn sync_wal(&mut self) {
+        self.outbox.deadline = None;
+        if self.halted {
+            return;
+        }
+        if self.wal.unsynced() > 0 {
+            if let Err(e) = self.wal.sync() {
+                self.halt(&e);
+                return;
+            }
+        }
+        self.flush_journal();
+
+        for event in self.outbox.events.drain(..) {
+            let _ = self.event_broadcaster.send(event);
+        }
+        for (responder, events) in self.outbox.responses.drain(..) {
+            respond(responder, events);
+        }
+    }
+
+    // Kirim event ke subscriber. Selama batch group commit masih terbuka, event ditahan di outbox
+    // agar urutannya tetap sama dengan event dari entry yang belum durable
+    fn publish(&mut self, event: EngineEvent) {
+        let event = MarketEvent { symbol: self.symbol.clone(), event };
+        if self.wal.unsynced() == 0 {
+            let _ = self.event_broadcaster.send(event);
+        } else {
+            self.outbox.events.push(event);
+        }
+    }
+
+    // Order yang gagal validasi: tidak ditulis ke WAL, hanya dilaporkan ke pengirim dan subscriber
+    fn reject(&mut self, order_id: u64, user_id: u64, reason: RejectReason) -> Vec<EngineEvent> {
+        let event = EngineEvent::OrderRejected { id: order_id, user_id, reason };
+        self.publish(event.clone());
+        vec![event]
+    }
+
+    // Cancel/amend hanya untuk order yang masih hidup dan milik user yang request
+    fn authorize(owner: Option<u64>, user_id: u64) -> Result<(), RejectReason> {
+        match owner {
+            None => Err(RejectReason::OrderNotFound),
+            Some(owner) if owner != user_id => Err(RejectReason::NotOrderOwner),
+            Some(_) => Ok(()),
+        }
+    }
+
+    // Validasi order baru terhadap InstrumentSpec market ini
+    fn validate_order(&self, price: u64, quantity: u64, display_quantity: Option<u64>) -> Result<(), RejectReason> {
+        self.spec.validate_limit(price, quantity)?;
+        if let Some(display_quantity) = display_quantity {
+            self.spec.validate_display_quantity(display_quantity)?;
+        }
+        Ok(())
+    }
+
+    fn validate_stop(&self, trigger_price: u64, limit_price: Option<u64>, quantity: u64) -> Result<(), RejectReason> {
+        self.spec.validate_price(trigger_price)?;
+        match limit_price {
+            Some(limit_price) => self.spec.validate_limit(limit_price, quantity),
+            None => self.spec.validate_quantity(quantity),
+        }
+    }
+
+    // Validasi order dan dana sebelum Write-Ahead: order yang ditolak tidak pernah masuk WAL
+    fn submit(
+        &mut self,
+        order_id: u64,
+        user_id: u64,
+        entry: LogEntry,
+        validation: Result<(), RejectReason>
+    ) -> Result<Vec<EngineEvent>, EngineError> {
+        // Retry dengan order ID yang sama mendapat hasil aslinya, tanpa WAL dan tanpa broadcast ulang
+        match self.orders.check(&entry, &self.book) {
+            Submission::New => {}
+            Submission::Retry(events) => return Ok(events),
+            Submission::Duplicate(reason) => return Ok(self.reject(order_id, user_id, reason)),
+        }
+
+        // Cek saldo sampai entry diterapkan tidak boleh diselingi market lain yang memakai saldo yang sama
+        let accounts = self.ledger.accounts().clone();
+        let _admission = accounts.admit();
+        let mut entry = entry;
+        let checked = validation.and_then(|_| {
+            if let LogEntry::PlaceMarket { user_id, side: Side::Bid, quantity, stp, locked_quote, .. } = &mut entry {
+                *locked_quote = Some(self.market_budget(*user_id, *quantity, *stp)?);
+            }
+            match Self::reservation(&self.book, self.fees.schedule(), &entry) {
+                Some(reservation) if !self.ledger.can_reserve(&reservation) => Err(RejectReason::InsufficientBalance),
+                _ => Ok(()),
+            }
+        });
+
+        match checked {
+            Ok(()) => self.commit(entry),
+            Err(reason) => Ok(self.reject(order_id, user_id, reason)),
+        }
+    }
+
+    // Sapu GTD order yang sudah lewat waktunya. Hanya menulis ke WAL jika memang ada yang kedaluwarsa
+    fn sweep_expired(&mut self) {
+        let now = self.clock.now();
+        if !self.halted && self.book.next_expiry().is_some_and(|expires_at| expires_at <= now) {
+            let _ = self.commit(LogEntry::Expire { timestamp: now });
+        }
+    }
+
+    // Snapshot state setelah entry WAL terakhir. Dijalankan di antara command, jadi state selalu konsisten
+    fn take_snapshot(&mut self) -> io::Result<u64> {
+        // Snapshot tidak boleh mendahului WAL yang durable
+        if self.halted {
+            return Err(io::Error::other("market is halted"));
+        }
+        self.sync_wal();
+        self.journal.sync()?;
+        let seq = self.wal.last_seq();
+        let next_event_seq = self.journal.next_event_seq();
+        let path = self.snapshots.save(seq, next_event_seq, &self.book, &self.ledger, &self.fees, &self.orders)?;
+        self.snapshot_seq = seq;
+        println!("[{}] Snapshot at seq {} written to {}", self.symbol, seq, path.display());
+
+        // Segment hanya dibuang jika tercakup snapshot tertua yang masih disimpan,
+        // agar fallback ke snapshot lama (jika yang terbaru rusak) tetap punya WAL lanjutannya
+        if let Some(oldest) = self.snapshots.oldest()? {
+            let archive = self.wal_archive_dir.as_deref().map(Path::new);
+            let removed = self.wal.compact(oldest, archive)?;
+            if removed > 0 {
+                println!("[{}] Compacted {} WAL segments up to seq {}", self.symbol, removed, oldest);
+            }
+        }
+        Ok(seq)
+    }
+
+    // Snapshot otomatis setelah snapshot_interval entry baru. Kegagalan hanya dicatat, WAL tetap sumber kebenaran
+    fn snapshot_if_due(&mut self) {
+        if self.halted || self.snapshot_interval == 0 || self.wal.last_seq() < self.snapshot_seq + self.snapshot_interval {
+            return;
+        }
+        if let Err(e) = self.take_snapshot() {
+            eprintln!("[{}] WARNING: Failed to write snapshot: {}", self.symbol, e);
         }
     }
 
     // Ini akan dijalankan di tokio::spawn_blocking atau thread dedikasi
     pub async fn run(mut self) {
-        println!("Market Engine Started & Persisted.");
-
-        while let Some(cmd) = self.receiver.recv().await {
-            match cmd {
-                Command::PlaceOrder { user_id, order_id, side, price, quantity, responder } => {
-                    // 1. (WAL) Persistence First (Write-Ahead)
-                    let log_entry = LogEntry::Place { order_id, user_id, side, price, quantity };
-                    
-                    if let Err(e) = self.wal.write_entry(&log_entry) {
-                        eprintln!("CRITICAL: Failed to write to WAL: {}", e);
-                    }
-
-                    // 2. Mmemory Execution
-                    let events = self.book.place_limit_order(order_id, user_id, side, price, quantity);
-
-                    // 3. Broadcast (Pub/Sub) 
-                    // Kirim copy event ke semua subscriber WebSocket
-                    for event in &events {
-                        // Hanya broadcast event publik (Trade). Private info (OrderPlaced) opsional.
-                        // Di sini broadcast semuanya agar dashboard terlihat hidup
-                        let _ = self.event_broadcaster.send(event.clone());
-                    }
-
-                    // 4. Respond (gRPC)
-                    let _ = responder.send(events);
+        println!("[{}] Market Engine Started & Persisted.", self.symbol);
+
+        let mut expiry_timer = tokio::time::interval(EXPIRY_SWEEP_INTERVAL);
+
+        loop {
+            let deadline = self.outbox.deadline;
+            tokio::select! {
+                cmd = self.receiver.recv() => {
+                    let Some(cmd) = cmd else { break };
+                    self.handle_command(cmd);
+                }
+                _ = expiry_timer.tick() => {
+                    self.sweep_expired();
+                }
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)), if deadline.is_some() => {
+                    self.sync_wal();
+                }
+            }
+            if self.wal.batch_full() {
+                self.sync_wal();
+            }
+            self.snapshot_if_due();
+        }
+
+        // Channel ditutup: batch terakhir tetap di-sync sebelum processor berhenti
+        self.sync_wal();
+        if let Err(e) = self.journal.sync() {
+            eprintln!("[{}] WARNING: Failed to sync event journal on shutdown: {}", self.symbol, e);
+        }
+    }
+
+    fn handle_command(&mut self, cmd: Command) {
+        let timestamp = self.clock.now();
+
+        match cmd {
+            Command::PlaceOrder {
+                user_id, order_id, side, price, quantity, time_in_force, post_only, stp, display_quantity, client_order_id, responder
+            } => {
+                let validation = self.validate_order(price, quantity, display_quantity);
+                let result = self.submit(order_id, user_id, LogEntry::Place {
+                    order_id, user_id, side, price, quantity, time_in_force, post_only, stp, display_quantity, client_order_id, timestamp
+                }, validation);
+
+                // 4. Respond (gRPC)
+                self.respond(responder, result);
+            }
+
+            Command::PlaceMarketOrder { user_id, order_id, side, quantity, stp, client_order_id, responder } => {
+                let validation = self.spec.validate_quantity(quantity);
+                // locked_quote market buy diisi oleh submit() di dalam admission
+                let result = self.submit(order_id, user_id, LogEntry::PlaceMarket {
+                    order_id, user_id, side, quantity, stp, client_order_id, locked_quote: None, timestamp
+                }, validation);
+                self.respond(responder, result);
+            }
+
+            Command::CancelOrder { user_id, order_id, client_order_id, responder } => {
+                let resolved = match client_order_id {
+                    Some(client_order_id) => self.orders.resolve(user_id, &client_order_id),
+                    None => Some(order_id),
+                };
+                // Cancel yang pasti gagal tidak ditulis ke WAL
+                let result = match Self::authorize(resolved.and_then(|id| self.book.owner(id)), user_id) {
+                    Ok(()) => self.commit(LogEntry::Cancel { order_id: resolved.unwrap_or(order_id), user_id }),
+                    Err(reason) => Ok(self.reject(resolved.unwrap_or(order_id), user_id, reason)),
+                };
+                self.respond(responder, result);
+            }
+
+            Command::MassCancel { user_id, filter, responder } => {
+                let result = self.commit(LogEntry::CancelAll { user_id, filter });
+                self.respond(responder, result);
+            }
+
+            Command::AmendOrder { user_id, order_id, price, quantity, responder } => {
+                // Satu entry WAL untuk amend (bukan Cancel + Place)
+                let owner = self.book.order(order_id).map(|order| order.user_id);
+                let validation = Self::authorize(owner, user_id)
+                    .and_then(|_| self.validate_order(price, quantity, None));
+                let result = self.submit(order_id, user_id, LogEntry::Amend {
+                    order_id, user_id, price, quantity, timestamp
+                }, validation);
+                self.respond(responder, result);
+            }
+
+            Command::PlaceStopOrder {
+                user_id, order_id, side, trigger_price, limit_price, quantity, stp, client_order_id, responder
+            } => {
+                let validation = self.validate_stop(trigger_price, limit_price, quantity);
+                let result = self.submit(order_id, user_id, LogEntry::PlaceStop {
+                    order_id, user_id, side, trigger_price, limit_price, quantity, stp, client_order_id, timestamp
+                }, validation);
+                self.respond(responder, result);
+            }
+
+            Command::Deposit { journal_id, user_id, asset, amount, responder } => {
+                let accounts = self.ledger.accounts().clone();
+                let _admission = accounts.admit();
+                // Retry dari settlement layer (lewat market mana pun): sudah diterapkan, tidak perlu masuk WAL lagi
+                if self.ledger.is_journaled(journal_id) {
+                    self.respond(responder, Ok(Vec::new()));
+                    return;
                 }
-                
-                Command::CancelOrder { user_id, order_id, responder } => {
-                    // 1. Persistence First
-                    let log_entry = LogEntry::Cancel { order_id, user_id };
-                    
-                    if let Err(e) = self.wal.write_entry(&log_entry) {
-                        eprintln!("CRITICAL: Failed to write to WAL: {}", e);
-                    }
-
-                    // 2. Memory Execution
-                    let events = self.book.cancel_order(order_id, user_id);
-                    
-                    // Broadcast Cancel
-                    for event in &events {
-                        let _ = self.event_broadcaster.send(event.clone());
-                    }
-
-                    let _ = responder.send(events);
+                let result = self.commit(LogEntry::Deposit { journal_id, user_id, asset, amount, timestamp });
+                self.respond(responder, result);
+            }
+
+            Command::Withdraw { journal_id, user_id, asset, amount, responder } => {
+                let accounts = self.ledger.accounts().clone();
+                let _admission = accounts.admit();
+                if self.ledger.is_journaled(journal_id) {
+                    self.respond(responder, Ok(Vec::new()));
+                    return;
                 }
 
-                Command::GetDepth { limit, responder } => {
-                    // Read-only command tidak perlu ditulis ke WAL
-                    let depth = self.book.get_depth(limit);
-                    let _ = responder.send(depth);
+                // Cek sebelum Write-Ahead: withdrawal yang ditolak tidak pernah masuk WAL
+                let available = self.ledger.available(user_id, asset);
+                if available < amount {
+                    self.respond(responder, Ok(vec![EngineEvent::WithdrawalRejected { user_id, asset, amount, available }]));
+                    return;
                 }
+
+                let result = self.commit(LogEntry::Withdraw { journal_id, user_id, asset, amount, timestamp });
+                self.respond(responder, result);
+            }
+
+            // Query dijawab dari state di memory tanpa menutup batch (tidak ada fsync per query).
+            // Dengan GroupCommit/FlushOnly hasilnya bisa memuat entry yang belum durable, termasuk saat market
+            // halted; balasan dan event entry tersebut tetap ditahan sampai durable
+            Command::GetOrder { user_id, order_id, client_order_id, responder } => {
+                let resolved = match client_order_id {
+                    Some(client_order_id) => self.orders.resolve(user_id, &client_order_id),
+                    None => Some(order_id),
+                };
+                let status = resolved
+                    .and_then(|id| self.orders.status(id, &self.book))
+                    .filter(|status| status.user_id == user_id)
+                    .ok_or(EngineError::OrderNotFound { order_id: resolved.unwrap_or(order_id) });
+                let _ = responder.send(status);
+            }
+
+            Command::ListOpenOrders { user_id, responder } => {
+                let _ = responder.send(self.orders.open_orders(user_id, &self.book));
+            }
+
+            Command::GetDepth { limit, responder } => {
+                // Read-only command tidak perlu ditulis ke WAL
+                let depth = self.book.get_depth(limit);
+                let _ = responder.send(depth);
+            }
+
+            Command::Snapshot { responder } => {
+                let _ = responder.send(self.take_snapshot());
+            }
+
+            Command::Resume { responder } => {
+                let _ = responder.send(self.resume());
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// Kirim hasil ke pengirim command: penolakan dikembalikan sebagai EngineError, bukan daftar event kosong
+fn respond(responder: Responder, result: Result<Vec<EngineEvent>, EngineError>) {
+    let result = result.and_then(|events| match EngineError::from_events(&events) {
+        Some(error) => Err(error),
+        None => Ok(events),
+    });
+    let _ = responder.send(result);
+}
@@ -0,0 +1,172 @@
+// crates/engine-core/src/registry.rs
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::sync::{mpsc, broadcast};
+use crate::instrument::InstrumentSpec;
+use crate::ledger::SharedAccounts;
+use crate::processor::{Command, MarketConfig, MarketEvent, MarketProcessor};
+use crate::wal::WalError;
+
+// Kapasitas antrian command per market
+const COMMAND_BUFFER: usize = 1024;
+
+// WAL lama sebelum multi-market: satu file untuk satu market tanpa symbol, record tanpa framing
+pub const LEGACY_WAL_PATH: &str = "velocity.wal";
+
+// Tolak startup selama WAL lama masih ada: entry-nya tidak ikut ter-replay ke market mana pun,
+// jadi menjalankan engine di atasnya akan kehilangan order dan saldo secara diam-diam
+pub fn check_legacy_wal(path: impl AsRef<Path>) -> Result<(), WalError> {
+    let path = path.as_ref();
+    match path.try_exists()? {
+        true => Err(WalError::LegacyWal { path: path.to_path_buf() }),
+        false => Ok(()),
+    }
+}
+
+// Registry semua instrument yang aktif. Setiap market punya MarketProcessor (actor),
+// OrderBook dan WAL sendiri, jadi market yang sibuk tidak memblokir market lain.
+// Saldo user dipakai bersama semua market di registry (key = symbol aset)
+#[derive(Default)]
+pub struct MarketRegistry {
+    markets: BTreeMap<String, Market>,
+    accounts: SharedAccounts,
+}
+
+struct Market {
+    sender: mpsc::Sender<Command>,
+    // Reference data instrument (tidak berubah selama processor berjalan)
+    spec: InstrumentSpec,
+}
+
+impl MarketRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Recover market dari WAL-nya lalu jalankan processor di background task.
+    // Semua market berbagi satu broadcaster, event ditandai dengan symbol.
+    // Gagal jika WAL market tidak bisa di-recover (market tidak didaftarkan)
+    pub fn spawn(&mut self, config: MarketConfig, broadcaster: broadcast::Sender<MarketEvent>) -> Result<(), WalError> {
+        let (tx, rx) = mpsc::channel(COMMAND_BUFFER);
+        let symbol = config.symbol.clone();
+        let spec = config.spec;
+
+        let processor = MarketProcessor::new(config, rx, broadcaster)?.with_accounts(self.accounts.clone());
+        tokio::spawn(async move {
+            processor.run().await;
+        });
+
+        self.markets.insert(symbol, Market { sender: tx, spec });
+        Ok(())
+    }
+
+    // Channel command untuk market `symbol` (None jika symbol tidak terdaftar)
+    pub fn sender(&self, symbol: &str) -> Option<&mpsc::Sender<Command>> {
+        self.markets.get(symbol).map(|market| &market.sender)
+    }
+
+    pub fn spec(&self, symbol: &str) -> Option<&InstrumentSpec> {
+        self.markets.get(symbol).map(|market| &market.spec)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.markets.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RejectReason, Side};
+    use crate::ledger::Asset;
+    use tokio::sync::oneshot;
+    use crate::test_support::{deposit_command, place_command, symbol_config, TempDir};
+
+    #[tokio::test]
+    async fn test_commands_are_routed_by_symbol() {
+        let dir = TempDir::new("registry_routing");
+        let (broadcast_tx, mut events) = broadcast::channel(64);
+        let mut registry = MarketRegistry::new();
+        for symbol in ["SOL_USDC", "BTC_USDC"] {
+            registry.spawn(symbol_config(&dir, symbol), broadcast_tx.clone()).unwrap();
+        }
+        assert_eq!(registry.symbols().collect::<Vec<_>>(), vec!["BTC_USDC", "SOL_USDC"]);
+
+        // Symbol yang tidak terdaftar tidak punya channel (api-server menjawab NOT_FOUND)
+        assert!(registry.sender("ETH_USDC").is_none());
+        assert!(registry.spec("ETH_USDC").is_none());
+        assert!(registry.sender("sol_usdc").is_none());
+
+        let (command, reply) = deposit_command(1, 1, Asset::Base, 5);
+        registry.sender("SOL_USDC").unwrap().send(command).await.unwrap();
+        assert!(reply.await.unwrap().is_ok());
+        let (command, reply) = place_command(1, 1, Side::Ask, 10, 5);
+        registry.sender("SOL_USDC").unwrap().send(command).await.unwrap();
+        assert!(reply.await.unwrap().is_ok());
+
+        // Order hanya masuk buku market tujuan, event ditandai symbol asalnya
+        for (symbol, asks) in [("SOL_USDC", 1), ("BTC_USDC", 0)] {
+            let (responder, depth) = oneshot::channel();
+            registry.sender(symbol).unwrap().send(Command::GetDepth { limit: 10, responder }).await.unwrap();
+            assert_eq!(depth.await.unwrap().0.len(), asks, "{}", symbol);
+        }
+        while let Ok(event) = events.try_recv() {
+            assert_eq!(event.symbol, "SOL_USDC");
+        }
+
+        // Order id yang sama di market lain adalah order yang berbeda
+        let (command, reply) = deposit_command(2, 2, Asset::Quote, 50);
+        registry.sender("BTC_USDC").unwrap().send(command).await.unwrap();
+        assert!(reply.await.unwrap().is_ok());
+        let (command, reply) = place_command(1, 2, Side::Bid, 10, 5);
+        registry.sender("BTC_USDC").unwrap().send(command).await.unwrap();
+        assert!(reply.await.unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_legacy_wal_refuses_startup() {
+        let dir = TempDir::new("registry_legacy");
+        let path = dir.join(LEGACY_WAL_PATH);
+        assert!(check_legacy_wal(&path).is_ok());
+
+        std::fs::create_dir_all(dir.path()).unwrap();
+        std::fs::write(&path, b"old log").unwrap();
+        match check_legacy_wal(&path) {
+            Err(WalError::LegacyWal { path: found }) => assert_eq!(found, path),
+            other => panic!("Harusnya ditolak, dapat {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quote_balance_is_shared_across_markets() {
+        let dir = TempDir::new("registry_accounts");
+        let (broadcast_tx, _) = broadcast::channel(64);
+        let mut registry = MarketRegistry::new();
+        for symbol in ["SOL_USDC", "BTC_USDC"] {
+            registry.spawn(symbol_config(&dir, symbol), broadcast_tx.clone()).unwrap();
+        }
+        let send = |symbol: &str, (command, reply)| {
+            registry.sender(symbol).unwrap().try_send(command).unwrap();
+            reply
+        };
+
+        // USDC yang didepositkan lewat SOL_USDC mendanai bid di BTC_USDC
+        assert!(send("SOL_USDC", deposit_command(1, 7, Asset::Quote, 1_000)).await.unwrap().is_ok());
+        assert!(send("BTC_USDC", place_command(1, 7, Side::Bid, 10, 60)).await.unwrap().is_ok());
+
+        // Sisanya 400 untuk kedua market
+        let rejected = send("SOL_USDC", place_command(2, 7, Side::Bid, 10, 41)).await.unwrap();
+        assert_eq!(rejected.unwrap_err().reason(), RejectReason::InsufficientBalance);
+        assert!(send("SOL_USDC", place_command(2, 7, Side::Bid, 10, 40)).await.unwrap().is_ok());
+
+        // Journal id juga berlaku lintas market: retry lewat market lain tidak mengkredit lagi
+        assert!(send("BTC_USDC", deposit_command(1, 7, Asset::Quote, 1_000)).await.unwrap().unwrap().is_empty());
+
+        // Base tetap per aset: SOL tidak bisa dijual di BTC_USDC
+        assert!(send("SOL_USDC", deposit_command(2, 7, Asset::Base, 5)).await.unwrap().is_ok());
+        let rejected = send("BTC_USDC", place_command(3, 7, Side::Ask, 10, 5)).await.unwrap();
+        assert_eq!(rejected.unwrap_err().reason(), RejectReason::InsufficientBalance);
+        assert!(send("SOL_USDC", place_command(3, 7, Side::Ask, 10, 5)).await.unwrap().is_ok());
+    }
+}
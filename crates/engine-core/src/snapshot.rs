@@ -0,0 +1,205 @@
+// crates/engine-core/src/snapshot.rs
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use crate::OrderBook;
+use crate::fees::FeeEngine;
+use crate::ledger::Ledger;
+use crate::orders::OrderRegistry;
+
+// Format file (little endian): [len: u64][crc32: u32][payload: bincode(Snapshot)]
+const HEADER_LEN: usize = 12;
+const EXTENSION: &str = "snap";
+// Snapshot lama yang disimpan sebagai cadangan jika yang terbaru rusak
+const RETAINED_SNAPSHOTS: usize = 3;
+
+// State lengkap satu market setelah entry WAL `seq` diterapkan.
+// Slab, antrian per level (prioritas waktu) dan Trigger Store disimpan apa adanya
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub seq: u64,
+    // Seq event berikutnya di journal event pada titik snapshot (posisi journal yang harus dilanjutkan)
+    pub next_event_seq: u64,
+    pub book: OrderBook,
+    pub ledger: Ledger,
+    pub fees: FeeEngine,
+    pub orders: OrderRegistry,
+}
+
+// Versi pinjaman dari Snapshot untuk ditulis tanpa clone state processor (urutan field harus sama)
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    seq: u64,
+    next_event_seq: u64,
+    book: &'a OrderBook,
+    ledger: &'a Ledger,
+    fees: &'a FeeEngine,
+    orders: &'a OrderRegistry,
+}
+
+// Direktori snapshot satu market: snapshot-<seq>.snap, satu file per snapshot
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    // Tulis snapshot secara atomik (file sementara + rename), lalu buang snapshot yang terlalu lama
+    pub fn save(
+        &self,
+        seq: u64,
+        next_event_seq: u64,
+        book: &OrderBook,
+        ledger: &Ledger,
+        fees: &FeeEngine,
+        orders: &OrderRegistry
+    ) -> io::Result<PathBuf> {
+        let payload = bincode::serialize(&SnapshotRef { seq, next_event_seq, book, ledger, fees, orders })
+            .map_err(io::Error::other)?;
+
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(seq);
+        let tmp = path.with_extension("tmp");
+
+        let mut file = File::create(&tmp)?;
+        file.write_all(&(payload.len() as u64).to_le_bytes())?;
+        file.write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+        file.write_all(&payload)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+
+        // Sinkronkan direktori agar rename ikut durable (tidak didukung di semua platform)
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+
+        for (_, old) in self.list()?.into_iter().rev().skip(RETAINED_SNAPSHOTS) {
+            fs::remove_file(old)?;
+        }
+
+        Ok(path)
+    }
+
+    // Snapshot valid terbaru. Snapshot yang rusak (checksum salah, terpotong) dilewati,
+    // recovery mundur ke snapshot sebelumnya. None = belum ada snapshot
+    pub fn load_latest(&self) -> io::Result<Option<Snapshot>> {
+        for (seq, path) in self.list()?.into_iter().rev() {
+            match Self::load(&path) {
+                Ok(snapshot) if snapshot.seq == seq => return Ok(Some(snapshot)),
+                Ok(snapshot) => {
+                    eprintln!("WARNING: skipping snapshot {}: contains seq {}", path.display(), snapshot.seq);
+                }
+                Err(e) => eprintln!("WARNING: skipping invalid snapshot {}: {}", path.display(), e),
+            }
+        }
+        Ok(None)
+    }
+
+    // Seq snapshot tertua yang masih disimpan (batas aman untuk membuang WAL)
+    pub fn oldest(&self) -> io::Result<Option<u64>> {
+        Ok(self.list()?.first().map(|&(seq, _)| seq))
+    }
+
+    fn load(path: &Path) -> io::Result<Snapshot> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < HEADER_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated header"));
+        }
+
+        let len = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let crc = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        let payload = &bytes[HEADER_LEN..];
+        if payload.len() as u64 != len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated payload"));
+        }
+        if crc32fast::hash(payload) != crc {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "checksum mismatch"));
+        }
+
+        bincode::deserialize(payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Semua file snapshot di direktori, urut berdasarkan seq
+    fn list(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut snapshots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == EXTENSION) {
+                let seq = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str()?.strip_prefix("snapshot-")?.parse().ok());
+                if let Some(seq) = seq {
+                    snapshots.push((seq, path));
+                }
+            }
+        }
+        snapshots.sort();
+        Ok(snapshots)
+    }
+
+    fn path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("snapshot-{:020}.{}", seq, EXTENSION))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EngineEvent, OrderId, Side, StpPolicy};
+    use crate::stops::StopOrder;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn test_snapshot_round_trip_keeps_queue_priority() {
+        let dir = TempDir::new("snapshot");
+        let store = SnapshotStore::new(dir.path());
+
+        // Slot slab 0 dibebaskan lalu dipakai ulang, jadi urutan antrian != urutan slot
+        let mut book = OrderBook::new();
+        book.place_limit_order(1, 1, Side::Ask, 100, 5);
+        book.place_limit_order(2, 2, Side::Ask, 100, 5);
+        book.cancel_order(1, 1);
+        book.place_limit_order(3, 3, Side::Ask, 100, 5);
+        book.place_stop_order(StopOrder {
+            id: 4, user_id: 4, side: Side::Bid, trigger_price: 100, limit_price: None,
+            quantity: 1, stp: StpPolicy::default(), budget: None,
+        });
+
+        let (ledger, fees, orders) = (Ledger::new(), FeeEngine::default(), OrderRegistry::new());
+        store.save(7, 1, &book, &ledger, &fees, &orders).unwrap();
+        store.save(9, 1, &book, &ledger, &fees, &orders).unwrap();
+
+        // Snapshot terbaru rusak: recovery mundur ke snapshot sebelumnya
+        let latest = store.path(9);
+        let mut bytes = std::fs::read(&latest).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        std::fs::write(&latest, bytes).unwrap();
+
+        let snapshot = store.load_latest().unwrap().unwrap();
+        assert_eq!(snapshot.seq, 7);
+        let mut restored = snapshot.book;
+        assert!(restored.has_stop(4));
+
+        let trades: Vec<OrderId> = restored
+            .place_limit_order(5, 5, Side::Bid, 100, 10)
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::TradeExecuted { maker_id, .. } => Some(*maker_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(trades, vec![2, 3]);
+    }
+}
@@ -0,0 +1,152 @@
+// crates/engine-core/src/stops.rs
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use serde::{Serialize, Deserialize};
+use crate::{OrderId, UserId, Price, Quantity, Side, StpPolicy, EngineEvent, CancelReason};
+
+// Order kondisional: belum masuk buku sampai harga trade menyentuh trigger_price
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StopOrder {
+    pub id: OrderId,
+    pub user_id: UserId,
+    pub side: Side,
+    pub trigger_price: Price,
+    // None = Stop-Market, Some(p) = Stop-Limit dengan harga limit p
+    pub limit_price: Option<Price>,
+    pub quantity: Quantity,
+    pub stp: StpPolicy,
+    // Stop-Market buy: batas total quote yang boleh dibelanjakan saat ter-trigger
+    pub budget: Option<u64>,
+}
+
+impl StopOrder {
+    pub fn cancelled(&self, reason: CancelReason) -> EngineEvent {
+        EngineEvent::StopCancelled {
+            id: self.id,
+            user_id: self.user_id,
+            side: self.side,
+            quantity: self.quantity,
+            reason,
+        }
+    }
+}
+
+// Trigger Store: terpisah dari bids/asks, tidak terlihat di depth
+// Buy stop trigger saat harga trade >= trigger, Sell stop saat harga trade <= trigger
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TriggerBook {
+    // Trigger Price -> Antrian (sequence, stop)
+    buy_stops: BTreeMap<Price, VecDeque<(u64, StopOrder)>>,
+    sell_stops: BTreeMap<Price, VecDeque<(u64, StopOrder)>>,
+    // Order ID -> (Side, Trigger Price) untuk cancel O(log N)
+    index: HashMap<OrderId, (Side, Price)>,
+    // Urutan kedatangan, dipakai sebagai tie-breaker saat banyak stop trigger bersamaan
+    sequence: u64,
+}
+
+impl TriggerBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains(&self, id: OrderId) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    pub fn get(&self, id: OrderId) -> Option<&StopOrder> {
+        let &(side, trigger_price) = self.index.get(&id)?;
+        self.levels(side)
+            .get(&trigger_price)?
+            .iter()
+            .find(|(_, stop)| stop.id == id)
+            .map(|(_, stop)| stop)
+    }
+
+    pub fn insert(&mut self, stop: StopOrder) {
+        self.sequence += 1;
+        let sequence = self.sequence;
+        let trigger_price = stop.trigger_price;
+
+        self.index.insert(stop.id, (stop.side, trigger_price));
+        self.levels_mut(stop.side)
+            .entry(trigger_price)
+            .or_default()
+            .push_back((sequence, stop));
+    }
+
+    // Semua stop yang menunggu (urutan tidak dijamin)
+    pub fn iter(&self) -> impl Iterator<Item = &StopOrder> {
+        self.buy_stops
+            .values()
+            .chain(self.sell_stops.values())
+            .flat_map(|queue| queue.iter().map(|(_, stop)| stop))
+    }
+
+    pub fn remove(&mut self, id: OrderId) -> Option<StopOrder> {
+        let (side, trigger_price) = self.index.remove(&id)?;
+        let levels = self.levels_mut(side);
+        let queue = levels.get_mut(&trigger_price)?;
+        let pos = queue.iter().position(|(_, stop)| stop.id == id)?;
+        let (_, stop) = queue.remove(pos)?;
+
+        if queue.is_empty() {
+            levels.remove(&trigger_price);
+        }
+
+        Some(stop)
+    }
+
+    // Ambil semua stop yang ter-trigger oleh rentang harga trade [low, high].
+    // Urutan deterministik: berdasarkan urutan kedatangan (sequence) stop tersebut
+    pub fn take_triggered(&mut self, low: Price, high: Price) -> Vec<StopOrder> {
+        let mut triggered = Vec::new();
+
+        // Buy stops dengan trigger <= harga tertinggi
+        let buy_keys: Vec<Price> = self.buy_stops.range(..=high).map(|(&p, _)| p).collect();
+        for price in buy_keys {
+            if let Some(queue) = self.buy_stops.remove(&price) {
+                triggered.extend(queue);
+            }
+        }
+
+        // Sell stops dengan trigger >= harga terendah
+        let sell_keys: Vec<Price> = self.sell_stops.range(low..).map(|(&p, _)| p).collect();
+        for price in sell_keys {
+            if let Some(queue) = self.sell_stops.remove(&price) {
+                triggered.extend(queue);
+            }
+        }
+
+        triggered.sort_by_key(|(seq, _)| *seq);
+
+        triggered
+            .into_iter()
+            .map(|(_, stop)| {
+                self.index.remove(&stop.id);
+                stop
+            })
+            .collect()
+    }
+
+    fn levels(&self, side: Side) -> &BTreeMap<Price, VecDeque<(u64, StopOrder)>> {
+        match side {
+            Side::Bid => &self.buy_stops,
+            Side::Ask => &self.sell_stops,
+        }
+    }
+
+    fn levels_mut(&mut self, side: Side) -> &mut BTreeMap<Price, VecDeque<(u64, StopOrder)>> {
+        match side {
+            Side::Bid => &mut self.buy_stops,
+            Side::Ask => &mut self.sell_stops,
+        }
+    }
+}
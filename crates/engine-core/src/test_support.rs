@@ -0,0 +1,85 @@
+// crates/engine-core/src/test_support.rs
+
+// Fixture bersama untuk test engine-core (hanya dikompilasi saat test)
+
+use std::path::{Path, PathBuf};
+use tokio::sync::oneshot;
+use crate::{EngineEvent, LogEntry, OrderId, PostOnly, Price, Quantity, Side, StpPolicy, TimeInForce, UserId};
+use crate::error::EngineError;
+use crate::ledger::Asset;
+use crate::processor::{Command, MarketConfig};
+
+// Balasan command yang mengubah state
+pub type Reply = oneshot::Receiver<Result<Vec<EngineEvent>, EngineError>>;
+
+// Entry WAL untuk limit order GTC tanpa opsi tambahan
+pub fn place_entry(
+    order_id: OrderId,
+    user_id: UserId,
+    side: Side,
+    price: Price,
+    quantity: Quantity,
+    client_order_id: Option<&str>,
+    timestamp: u64
+) -> LogEntry {
+    LogEntry::Place {
+        order_id, user_id, side, price, quantity,
+        time_in_force: TimeInForce::default(), post_only: PostOnly::default(), stp: StpPolicy::default(),
+        display_quantity: None, client_order_id: client_order_id.map(str::to_string), timestamp,
+    }
+}
+
+// Direktori sementara per test (unik per proses), dihapus saat drop walaupun test gagal
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("velocity_{}_test_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// Market "SOL_USDC" dengan WAL, snapshot dan journal di dalam `dir`/SOL_USDC
+pub fn market_config(dir: &TempDir) -> MarketConfig {
+    symbol_config(dir, "SOL_USDC")
+}
+
+// Market `symbol` dengan WAL, snapshot dan journal di dalam `dir`/`symbol`
+pub fn symbol_config(dir: &TempDir, symbol: &str) -> MarketConfig {
+    let mut config = MarketConfig::new(symbol);
+    let dir = dir.join(symbol);
+    config.wal_dir = dir.join("wal").to_string_lossy().into_owned();
+    config.snapshot_dir = dir.join("snapshots").to_string_lossy().into_owned();
+    config.journal_path = dir.join("events.journal").to_string_lossy().into_owned();
+    config
+}
+
+pub fn deposit_command(journal_id: u64, user_id: UserId, asset: Asset, amount: u64) -> (Command, Reply) {
+    let (responder, reply) = oneshot::channel();
+    (Command::Deposit { journal_id, user_id, asset, amount, responder }, reply)
+}
+
+// Limit order GTC tanpa opsi tambahan
+pub fn place_command(order_id: OrderId, user_id: UserId, side: Side, price: Price, quantity: Quantity) -> (Command, Reply) {
+    let (responder, reply) = oneshot::channel();
+    (Command::PlaceOrder {
+        user_id, order_id, side, price, quantity,
+        time_in_force: TimeInForce::default(), post_only: PostOnly::default(), stp: StpPolicy::default(),
+        display_quantity: None, client_order_id: None, responder,
+    }, reply)
+}
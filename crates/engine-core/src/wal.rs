@@ -1,60 +1,945 @@
 // crates/engine-core/src/wal.rs
 
-use std::path::Path;
-use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Write};
-use crate::LogEntry;
+use std::fs::{self, OpenOptions, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use crate::{LogEntry, MATCHING_VERSION};
+
+// Setiap segment diawali header (little endian):
+// [magic: 8 byte "VDEXWAL\0"][format version: u32][matching version: u32]
+// Format version = layout header/record, matching version = versi logika engine yang menulis log
+pub const FORMAT_VERSION: u32 = 1;
+const MAGIC: &[u8; 8] = b"VDEXWAL\0";
+const SEGMENT_HEADER_LEN: usize = 16;
+
+// Format satu record (little endian):
+// [len: u32][crc32: u32][seq: u64][payload: len byte bincode(LogEntry)]
+// CRC dihitung atas seq + payload, seq naik 1 per record
+pub(crate) const HEADER_LEN: usize = 16;
+// Batas wajar satu entry, len di atas ini pasti hasil korupsi
+pub(crate) const MAX_RECORD_LEN: usize = 16 * 1024 * 1024;
+
+// WAL adalah direktori berisi segment wal-<seq record pertama>.log.
+// Hanya segment terakhir (aktif) yang ditulis, segment lain sudah sealed (fsync) dan tidak pernah berubah
+const SEGMENT_PREFIX: &str = "wal-";
+const SEGMENT_EXTENSION: &str = "log";
+// File teks untuk operator: segment aktif + daftar segment sealed yang aman di-backup
+const MANIFEST: &str = "MANIFEST";
+
+#[derive(Debug, Error)]
+pub enum WalError {
+    #[error("WAL I/O error: {0}")]
+    Io(#[from] io::Error),
+    // Record rusak di tengah log: recovery tidak boleh lanjut karena entry sesudahnya akan hilang
+    #[error("WAL segment {} corrupted at byte offset {offset}: {detail}", segment.display())]
+    Corrupted { segment: PathBuf, offset: u64, detail: String },
+    // Entry sesudah snapshot tidak ada di log (state sesudahnya tidak bisa direkonstruksi)
+    #[error("WAL is missing entries: expected seq {expected}, found {found}")]
+    MissingEntries { expected: u64, found: u64 },
+    #[error("snapshot I/O error: {0}")]
+    Snapshot(io::Error),
+    #[error("WAL segment {} has unsupported format version {found} (supported: {expected})", segment.display())]
+    UnsupportedFormat { segment: PathBuf, found: u32, expected: u32 },
+    // Replay log dari logika matching lain akan menghasilkan state berbeda secara diam-diam
+    #[error(
+        "WAL segment {} was written by matching engine version {found}, this engine is version {expected}; \
+         a migration is required to replay it",
+        segment.display()
+    )]
+    IncompatibleEngine { segment: PathBuf, found: u32, expected: u32 },
+    // Hasil replay tidak sesuai dengan yang tercatat di WAL (e.g. penanda stop yang ter-trigger)
+    #[error("WAL replay diverges at seq {input_seq}: {detail}")]
+    ReplayDivergence { input_seq: u64, detail: String },
+    // Journal event tidak cocok dengan WAL (atau dengan hasil replay-nya)
+    #[error("event journal diverges from WAL at input seq {input_seq}: {detail}")]
+    JournalMismatch { input_seq: u64, detail: String },
+    // WAL single-market dari versi sebelum registry: tidak bisa di-replay, tapi tidak boleh diabaikan diam-diam
+    #[error(
+        "legacy single-market WAL {} found: it cannot be replayed by this engine; \
+         restore its state with the previous release and remove the file before starting",
+        path.display()
+    )]
+    LegacyWal { path: PathBuf },
+}
+
+// Jalur migrasi eksplisit untuk log dari matching version lama: setiap entry diubah sebelum di-replay
+// agar hasil replay di engine sekarang sesuai dengan yang dulu terjadi
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub from_version: u32,
+    pub migrate: fn(LogEntry) -> LogEntry,
+}
+
+// Kapan segment aktif di-seal dan segment baru dibuka (mana yang tercapai lebih dulu)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentPolicy {
+    pub max_bytes: u64,
+    pub max_entries: u64,
+}
+
+impl Default for SegmentPolicy {
+    fn default() -> Self {
+        Self { max_bytes: 64 * 1024 * 1024, max_entries: 1_000_000 }
+    }
+}
+
+// Kapan entry dianggap durable (dan boleh di-ack ke client)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    // fsync setiap entry: paling aman, satu fsync per order
+    #[default]
+    Fsync,
+    // Entry dikumpulkan lalu di-fsync sekali per batch, saat max_entries atau max_delay tercapai.
+    // Caller wajib menahan ack sampai sync() berhasil
+    GroupCommit { max_entries: usize, max_delay: Duration },
+    // Hanya flush ke page cache OS: selamat dari crash proses, tidak dari mati listrik
+    FlushOnly,
+}
+
+impl Durability {
+    // Group commit dengan batas default: 256 entry atau 2ms
+    pub fn group_commit() -> Self {
+        Durability::GroupCommit { max_entries: 256, max_delay: Duration::from_millis(2) }
+    }
+}
+
+// Segment terakhir saat recovery, ditulis lagi setelah tail yang sobek dipotong
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveSegment {
+    pub path: PathBuf,
+    pub first_seq: u64,
+    // Panjang prefix yang valid (termasuk header)
+    pub len: u64,
+    pub entries: u64,
+    pub matching_version: u32,
+}
+
+// Hasil recovery direktori WAL
+#[derive(Debug, Default)]
+pub struct WalRecovery {
+    // (seq, entry) urut sesuai log
+    pub entries: Vec<(u64, LogEntry)>,
+    pub last_seq: u64,
+    pub active: Option<ActiveSegment>,
+    // Byte record terakhir yang tidak lengkap (torn write saat crash)
+    pub torn_bytes: u64,
+}
+
+// Tujuan tulis segment aktif. File di produksi, bisa diganti writer yang gagal untuk test fail-closed
+pub trait SegmentWriter: Write + Send {
+    fn sync(&mut self) -> io::Result<()>;
+    // Potong segment ke `len` byte (membuang record yang gagal ditulis sebagian)
+    fn truncate(&mut self, len: u64) -> io::Result<()>;
+}
+
+impl SegmentWriter for File {
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_data()
+    }
+
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.set_len(len)
+    }
+}
 
 pub struct WalHandler {
-    writer: BufWriter<File>,
+    dir: PathBuf,
+    policy: SegmentPolicy,
+    durability: Durability,
+    writer: Box<dyn SegmentWriter>,
+    segment: PathBuf,
+    // Byte record yang sudah diterima di segment aktif (di file + di batch)
+    segment_bytes: u64,
+    segment_entries: u64,
+    // Byte record yang sudah sampai ke file. Sesudah ini hanya ada sisa record yang gagal ditulis
+    written: u64,
+    // Group commit: record yang sudah diterima tapi belum ditulis + fsync
+    batch: Vec<u8>,
+    next_seq: u64,
+    // Entry yang belum durable (hanya group commit)
+    unsynced: usize,
+    // Setelah write/sync gagal, isi file tidak pasti: semua penulisan ditolak sampai repair()
+    failed: bool,
 }
 
 impl WalHandler {
-    // Membuka atau membuat file WAL baru
-    pub fn new(path: &str) -> std::io::Result<Self> {
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .append(true)
-            .open(path)?;
+    // Buka WAL untuk ditulis setelah recovery: tail yang sobek dipotong, seq dilanjutkan.
+    // Segment baru dibuka jika belum ada segment atau seq tidak lagi menyambung dengan segment terakhir
+    pub fn open(
+        dir: impl AsRef<Path>,
+        recovery: &WalRecovery,
+        policy: SegmentPolicy,
+        durability: Durability
+    ) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let next_seq = recovery.last_seq + 1;
 
-        Ok(Self {
-            writer: BufWriter::new(file),
-        })
+        // Segment dari matching version lain tidak pernah ditulisi lagi, log baru selalu di segment baru
+        let (segment, writer, segment_bytes, segment_entries) = match &recovery.active {
+            Some(active)
+                if active.first_seq + active.entries == next_seq
+                    && active.matching_version == MATCHING_VERSION
+                    && active.len > 0 =>
+            {
+                let file = OpenOptions::new().write(true).open(&active.path)?;
+                file.set_len(active.len)?;
+                let file = OpenOptions::new().append(true).open(&active.path)?;
+                (active.path.clone(), Box::new(file) as Box<dyn SegmentWriter>, active.len, active.entries)
+            }
+            other => {
+                // Segment lama tetap dirapikan walaupun tidak ditulis lagi
+                if let Some(active) = other {
+                    OpenOptions::new().write(true).open(&active.path)?.set_len(active.len)?;
+                }
+                let path = segment_path(&dir, next_seq);
+                (path.clone(), create_segment(&path)?, SEGMENT_HEADER_LEN as u64, 0)
+            }
+        };
+
+        let wal = Self {
+            dir,
+            policy,
+            durability,
+            writer,
+            segment,
+            segment_bytes,
+            segment_entries,
+            written: segment_bytes,
+            batch: Vec::new(),
+            next_seq,
+            unsynced: 0,
+            failed: false,
+        };
+        wal.write_manifest()?;
+        Ok(wal)
     }
 
-    // Menulis satu entry ke disk
-    pub fn write_entry(&mut self, entry: &LogEntry) -> std::io::Result<()> {
-        // Serialize langsung ke buffer writer
-        bincode::serialize_into(&mut self.writer, entry)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    // Menulis satu entry ke disk. Dengan group commit entry baru durable setelah sync().
+    // Error = entry TIDAK tercatat (caller tidak boleh mengeksekusinya), WAL menolak penulisan sampai repair()
+    pub fn write_entry(&mut self, entry: &LogEntry) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other("WAL is halted after a failed write, repair required"));
+        }
+        let payload = bincode::serialize(entry).map_err(io::Error::other)?;
 
-        // Untuk HFT murni, biasanya flush dilakukan per batch atau interval waktu
-        // Pada skala seperti ini, flush setiap kali demi keamanan data
-        self.writer.flush()?;
+        if self.segment_entries > 0
+            && (self.segment_entries >= self.policy.max_entries
+                || self.segment_bytes + (HEADER_LEN + payload.len()) as u64 > self.policy.max_bytes)
+        {
+            self.guard(Self::rotate)?;
+        }
 
+        // Satu frame utuh per write, jadi kegagalan hanya bisa meninggalkan sisa frame ini di akhir file
+        let seq = self.next_seq;
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&checksum(seq, &payload).to_le_bytes());
+        frame.extend_from_slice(&seq.to_le_bytes());
+        frame.extend_from_slice(&payload);
+
+        match self.durability {
+            Durability::GroupCommit { .. } => {
+                self.batch.extend_from_slice(&frame);
+                self.unsynced += 1;
+            }
+            Durability::Fsync => self.guard(|wal| {
+                wal.writer.write_all(&frame)?;
+                wal.writer.sync()
+            })?,
+            Durability::FlushOnly => self.guard(|wal| wal.writer.write_all(&frame))?,
+        }
+        if self.batch.is_empty() {
+            // Fsync / FlushOnly: frame sudah sampai ke file
+            self.written += frame.len() as u64;
+        }
+
+        self.next_seq += 1;
+        self.segment_bytes += frame.len() as u64;
+        self.segment_entries += 1;
         Ok(())
     }
 
-    // Membaca ulang semua entry saat startup (Recovery)
-    pub fn read_all(path: &str) -> std::io::Result<Vec<LogEntry>> {
-        let path = Path::new(path);
-        if !path.exists() {
-            return Ok(Vec::new());
+    // Pastikan semua entry yang sudah diterima durable (tulis batch group commit + fsync)
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other("WAL is halted after a failed write, repair required"));
         }
+        self.guard(Self::flush_batch)
+    }
 
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
-        let mut entries =Vec:: new();
+    // Pulihkan writer setelah kegagalan (dipanggil operator saat resume): buang sisa record yang gagal
+    // dari akhir file, tulis ulang batch yang sudah diterima, lalu fsync
+    pub fn repair(&mut self) -> io::Result<()> {
+        self.writer.truncate(self.written)?;
+        self.flush_batch()?;
+        self.writer.sync()?;
+        self.failed = false;
+        Ok(())
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    // Ganti writer segment aktif (test: writer yang bisa dibuat gagal)
+    pub fn replace_writer(&mut self, writer: Box<dyn SegmentWriter>) {
+        self.writer = writer;
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    // Jumlah entry yang belum durable
+    pub fn unsynced(&self) -> usize {
+        self.unsynced
+    }
+
+    // Group commit: batch sudah mencapai max_entries dan harus di-sync sekarang
+    pub fn batch_full(&self) -> bool {
+        match self.durability {
+            Durability::GroupCommit { max_entries, .. } => self.unsynced >= max_entries,
+            _ => false,
+        }
+    }
 
-        // Loop baca file sampai EOF (End of File)
-        loop {
-            match bincode::deserialize_from(&mut reader) {
-                Ok(entry) => entries.push(entry),
-                Err(_) => break,
+    // Seq record terakhir yang sudah ditulis
+    pub fn last_seq(&self) -> u64 {
+        self.next_seq - 1
+    }
+
+    pub fn active_segment(&self) -> &Path {
+        &self.segment
+    }
+
+    // Segment sealed yang seluruh entry-nya <= covered_seq (sudah tercakup snapshot) dihapus,
+    // atau dipindah ke `archive` jika diisi. Segment aktif tidak pernah disentuh
+    pub fn compact(&mut self, covered_seq: u64, archive: Option<&Path>) -> io::Result<usize> {
+        let segments = list_segments(&self.dir)?;
+        let mut removed = 0;
+
+        for pair in segments.windows(2) {
+            let ((_, path), (next_first_seq, _)) = (&pair[0], &pair[1]);
+            if *next_first_seq > covered_seq + 1 || *path == self.segment {
+                break;
+            }
+            match archive {
+                Some(archive) => {
+                    fs::create_dir_all(archive)?;
+                    fs::rename(path, archive.join(path.file_name().unwrap_or_default()))?;
+                }
+                None => fs::remove_file(path)?,
+            }
+            removed += 1;
+        }
+
+        if removed > 0 {
+            self.write_manifest()?;
+        }
+        Ok(removed)
+    }
+
+    // Seal segment aktif (tulis batch + fsync) lalu lanjut menulis ke segment baru
+    fn rotate(&mut self) -> io::Result<()> {
+        self.flush_batch()?;
+        self.writer.sync()?;
+
+        let path = segment_path(&self.dir, self.next_seq);
+        self.writer = create_segment(&path)?;
+        self.segment = path;
+        self.segment_bytes = SEGMENT_HEADER_LEN as u64;
+        self.segment_entries = 0;
+        self.written = SEGMENT_HEADER_LEN as u64;
+        self.write_manifest()
+    }
+
+    fn flush_batch(&mut self) -> io::Result<()> {
+        if !self.batch.is_empty() {
+            self.writer.write_all(&self.batch)?;
+            if self.durability != Durability::FlushOnly {
+                self.writer.sync()?;
             }
+            self.written += self.batch.len() as u64;
+            self.batch.clear();
         }
+        self.unsynced = 0;
+        Ok(())
+    }
 
-        Ok(entries)
+    // Tandai WAL gagal jika operasi tulis error
+    fn guard<T>(&mut self, op: impl FnOnce(&mut Self) -> io::Result<T>) -> io::Result<T> {
+        let result = op(self);
+        if result.is_err() {
+            self.failed = true;
+        }
+        result
     }
-}
\ No newline at end of file
+
+    // Manifest ditulis atomik (file sementara + rename), jadi operator tidak pernah membaca manifest setengah jadi
+    fn write_manifest(&self) -> io::Result<()> {
+        let mut manifest = String::from("# active segment is being written, sealed segments are immutable\n");
+        for (_, path) in list_segments(&self.dir)? {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            match path == self.segment {
+                true => manifest.push_str(&format!("active {}\n", name)),
+                false => manifest.push_str(&format!("sealed {}\n", name)),
+            }
+        }
+
+        let tmp = self.dir.join(format!("{}.tmp", MANIFEST));
+        fs::write(&tmp, manifest)?;
+        fs::rename(tmp, self.dir.join(MANIFEST))
+    }
+
+    // Membaca ulang entry saat startup (Recovery). Segment yang seluruhnya <= after_seq
+    // (sudah tercakup snapshot) dilewati. Record terakhir segment aktif yang terpotong (torn write) dibuang,
+    // record rusak di tempat lain adalah error. Segment dari matching version lain hanya bisa di-replay
+    // lewat migrasi dari `migrations`
+    pub fn recover(dir: impl AsRef<Path>, after_seq: u64, migrations: &[Migration]) -> Result<WalRecovery, WalError> {
+        let segments = list_segments(dir.as_ref())?;
+        let skip = segments
+            .windows(2)
+            .take_while(|pair| pair[1].0 <= after_seq + 1)
+            .count();
+
+        let mut recovery = WalRecovery::default();
+        let last = segments.len().saturating_sub(1);
+
+        for (index, (first_seq, path)) in segments.into_iter().enumerate().skip(skip) {
+            if index > skip && first_seq != recovery.last_seq + 1 {
+                return Err(WalError::MissingEntries { expected: recovery.last_seq + 1, found: first_seq });
+            }
+
+            let bytes = fs::read(&path)?;
+            let Some(matching_version) = read_header(&path, &bytes)? else {
+                // Crash sebelum header segment baru selesai ditulis: segment dianggap kosong
+                if index != last {
+                    return Err(WalError::Corrupted {
+                        segment: path,
+                        offset: 0,
+                        detail: "incomplete header in sealed segment".to_string(),
+                    });
+                }
+                recovery.last_seq = first_seq.saturating_sub(1);
+                recovery.torn_bytes = bytes.len() as u64;
+                recovery.active = Some(ActiveSegment { path, first_seq, len: 0, entries: 0, matching_version: MATCHING_VERSION });
+                continue;
+            };
+
+            let (mut entries, len) = read_segment(&path, first_seq, &bytes)?;
+            let torn_bytes = (bytes.len() - len) as u64;
+
+            if matching_version != MATCHING_VERSION {
+                let migration = migrations
+                    .iter()
+                    .find(|migration| migration.from_version == matching_version)
+                    .ok_or_else(|| WalError::IncompatibleEngine {
+                        segment: path.clone(),
+                        found: matching_version,
+                        expected: MATCHING_VERSION,
+                    })?;
+                entries = entries.into_iter().map(|(seq, entry)| (seq, (migration.migrate)(entry))).collect();
+            }
+            if torn_bytes > 0 && index != last {
+                return Err(WalError::Corrupted {
+                    segment: path,
+                    offset: len as u64,
+                    detail: "incomplete record in sealed segment".to_string(),
+                });
+            }
+
+            recovery.last_seq = (first_seq + entries.len() as u64).saturating_sub(1);
+            if index == last {
+                recovery.torn_bytes = torn_bytes;
+                recovery.active = Some(ActiveSegment {
+                    path,
+                    first_seq,
+                    len: len as u64,
+                    entries: entries.len() as u64,
+                    matching_version,
+                });
+            }
+            recovery.entries.extend(entries);
+        }
+
+        Ok(recovery)
+    }
+}
+
+// Matching version dari header segment. None = header belum lengkap (torn)
+fn read_header(path: &Path, bytes: &[u8]) -> Result<Option<u32>, WalError> {
+    if bytes.len() < SEGMENT_HEADER_LEN {
+        return Ok(None);
+    }
+    if &bytes[0..8] != MAGIC {
+        return Err(WalError::Corrupted { segment: path.to_path_buf(), offset: 0, detail: "not a WAL segment".to_string() });
+    }
+
+    let format_version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    if format_version != FORMAT_VERSION {
+        return Err(WalError::UnsupportedFormat { segment: path.to_path_buf(), found: format_version, expected: FORMAT_VERSION });
+    }
+    Ok(Some(u32::from_le_bytes(bytes[12..16].try_into().unwrap())))
+}
+
+// Parse record satu segment (sesudah header). Mengembalikan entry yang valid dan panjang prefix valid-nya
+fn read_segment(path: &Path, first_seq: u64, bytes: &[u8]) -> Result<(Vec<(u64, LogEntry)>, usize), WalError> {
+    let mut entries = Vec::new();
+    let mut offset = SEGMENT_HEADER_LEN;
+
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        if rest.len() < HEADER_LEN {
+            break;
+        }
+
+        let len = u32::from_le_bytes(rest[0..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(rest[4..8].try_into().unwrap());
+        let seq = u64::from_le_bytes(rest[8..16].try_into().unwrap());
+        let expected = first_seq + entries.len() as u64;
+
+        if len > MAX_RECORD_LEN {
+            corrupted(path, rest, offset, format!("record length {} exceeds limit", len))?;
+            break;
+        }
+        if rest.len() < HEADER_LEN + len {
+            overrun(path, bytes, offset, len, expected + 1)?;
+            break;
+        }
+
+        let payload = &rest[HEADER_LEN..HEADER_LEN + len];
+        if checksum(seq, payload) != crc {
+            corrupted(path, rest, offset, format!("checksum mismatch in record seq {}", seq))?;
+            break;
+        }
+        if seq != expected {
+            corrupted(path, rest, offset, format!("expected seq {}, found {}", expected, seq))?;
+            break;
+        }
+
+        let entry = bincode::deserialize(payload).map_err(|e| WalError::Corrupted {
+            segment: path.to_path_buf(),
+            offset: offset as u64,
+            detail: format!("undecodable entry: {}", e),
+        })?;
+
+        entries.push((seq, entry));
+        offset += HEADER_LEN + len;
+    }
+
+    Ok((entries, offset))
+}
+
+// Semua segment di direktori WAL, urut berdasarkan seq record pertama
+fn list_segments(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut segments = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION) {
+            let first_seq = path
+                .file_stem()
+                .and_then(|stem| stem.to_str()?.strip_prefix(SEGMENT_PREFIX)?.parse().ok());
+            if let Some(first_seq) = first_seq {
+                segments.push((first_seq, path));
+            }
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+fn segment_path(dir: &Path, first_seq: u64) -> PathBuf {
+    dir.join(format!("{}{:020}.{}", SEGMENT_PREFIX, first_seq, SEGMENT_EXTENSION))
+}
+
+// Segment baru (atau segment kosong yang ditulis ulang) selalu dimulai dengan header versi engine ini
+fn create_segment(path: &Path) -> io::Result<Box<dyn SegmentWriter>> {
+    let mut file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+    file.write_all(MAGIC)?;
+    file.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    file.write_all(&MATCHING_VERSION.to_le_bytes())?;
+    file.sync_data()?;
+
+    Ok(Box::new(OpenOptions::new().append(true).open(path)?))
+}
+
+pub(crate) fn checksum(seq: u64, payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&seq.to_le_bytes());
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+// Frame yang melewati akhir file hanya torn tail jika memang yang terakhir. Jika sesudahnya masih ada
+// frame utuh dengan `next_seq`, length field-nya rusak dan memotong di sini akan membuang record yang durable
+pub(crate) fn overrun(path: &Path, bytes: &[u8], offset: usize, len: usize, next_seq: u64) -> Result<(), WalError> {
+    let next_seq_bytes = next_seq.to_le_bytes();
+    let found = (offset + HEADER_LEN..bytes.len()).any(|start| {
+        let Some(frame) = bytes.get(start..start + HEADER_LEN) else { return false };
+        if frame[8..16] != next_seq_bytes {
+            return false;
+        }
+        let len = u32::from_le_bytes(frame[0..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(frame[4..8].try_into().unwrap());
+        len <= MAX_RECORD_LEN
+            && bytes.get(start + HEADER_LEN..start + HEADER_LEN + len).is_some_and(|payload| checksum(next_seq, payload) == crc)
+    });
+
+    if found {
+        return Err(WalError::Corrupted {
+            segment: path.to_path_buf(),
+            offset: offset as u64,
+            detail: format!("record length {} runs past the next record (seq {})", len, next_seq),
+        });
+    }
+    Ok(())
+}
+
+// Sisa file yang seluruhnya nol adalah ruang yang sudah dialokasikan tapi belum sempat ditulis saat crash,
+// diperlakukan sama seperti tail yang terpotong. Selain itu record rusak = korupsi
+pub(crate) fn corrupted(path: &Path, rest: &[u8], offset: usize, detail: String) -> Result<(), WalError> {
+    if rest.iter().all(|&b| b == 0) {
+        return Ok(());
+    }
+    Err(WalError::Corrupted { segment: path.to_path_buf(), offset: offset as u64, detail })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tokio::sync::{broadcast, mpsc, oneshot};
+    use crate::{EngineEvent, Side};
+    use crate::error::EngineError;
+    use crate::ledger::Asset;
+    use crate::processor::{Command, MarketProcessor};
+    use crate::test_support::{deposit_command, market_config, place_command, TempDir};
+
+    #[test]
+    fn test_wal_recovery_truncates_torn_tail_and_rejects_corruption() {
+        let dir = TempDir::new("wal");
+
+        let mut wal = WalHandler::open(dir.path(), &WalRecovery::default(), SegmentPolicy::default(), Durability::default()).unwrap();
+        for timestamp in 1..=3 {
+            wal.write_entry(&LogEntry::Expire { timestamp }).unwrap();
+        }
+        let segment = wal.active_segment().to_path_buf();
+        drop(wal);
+        let bytes = std::fs::read(&segment).unwrap();
+        let record_len = (bytes.len() - SEGMENT_HEADER_LEN) / 3;
+
+        let recovery = WalHandler::recover(dir.path(), 0, &[]).unwrap();
+        assert_eq!((recovery.last_seq, recovery.torn_bytes), (3, 0));
+        assert_eq!(recovery.entries[2], (3, LogEntry::Expire { timestamp: 3 }));
+
+        // Crash di tengah penulisan record terakhir: tail dibuang, log lanjut dari seq 3
+        std::fs::write(&segment, &bytes[..bytes.len() - 5]).unwrap();
+        let recovery = WalHandler::recover(dir.path(), 0, &[]).unwrap();
+        assert_eq!(recovery.entries.len(), 2);
+        assert_eq!(recovery.active.as_ref().unwrap().len, (SEGMENT_HEADER_LEN + 2 * record_len) as u64);
+        assert_eq!(recovery.torn_bytes, record_len as u64 - 5);
+
+        let mut wal = WalHandler::open(dir.path(), &recovery, SegmentPolicy::default(), Durability::default()).unwrap();
+        wal.write_entry(&LogEntry::Expire { timestamp: 4 }).unwrap();
+        drop(wal);
+        let recovery = WalHandler::recover(dir.path(), 0, &[]).unwrap();
+        assert_eq!(recovery.entries[2], (3, LogEntry::Expire { timestamp: 4 }));
+
+        // Byte rusak di record tengah: error dengan offset record tersebut, bukan dipotong diam-diam
+        let mut corrupted = bytes.clone();
+        corrupted[SEGMENT_HEADER_LEN + record_len + 20] ^= 0xFF;
+        std::fs::write(&segment, &corrupted).unwrap();
+        match WalHandler::recover(dir.path(), 0, &[]) {
+            Err(WalError::Corrupted { offset, .. }) => assert_eq!(offset, (SEGMENT_HEADER_LEN + record_len) as u64),
+            other => panic!("Harusnya korupsi, dapat {:?}", other.map(|r| r.entries.len())),
+        }
+    }
+
+    #[test]
+    fn test_wal_corrupted_length_field_is_not_a_torn_tail() {
+        let dir = TempDir::new("wal_length");
+
+        let mut wal = WalHandler::open(dir.path(), &WalRecovery::default(), SegmentPolicy::default(), Durability::default()).unwrap();
+        for timestamp in 1..=3 {
+            wal.write_entry(&LogEntry::Expire { timestamp }).unwrap();
+        }
+        let segment = wal.active_segment().to_path_buf();
+        drop(wal);
+        let bytes = std::fs::read(&segment).unwrap();
+        let record_len = (bytes.len() - SEGMENT_HEADER_LEN) / 3;
+
+        // Bit flip di length record tengah membuat frame seolah melewati akhir file,
+        // tapi record sesudahnya masih utuh: korupsi, bukan torn tail yang boleh dipotong
+        let middle = SEGMENT_HEADER_LEN + record_len;
+        let mut corrupted = bytes.clone();
+        corrupted[middle + 1] ^= 0x10;
+        std::fs::write(&segment, &corrupted).unwrap();
+        match WalHandler::recover(dir.path(), 0, &[]) {
+            Err(WalError::Corrupted { offset, .. }) => assert_eq!(offset, middle as u64),
+            other => panic!("Harusnya korupsi, dapat {:?}", other.map(|r| r.entries.len())),
+        }
+
+        // Di record terakhir tidak bisa dibedakan dari torn write: dibuang seperti tail yang terpotong
+        let mut corrupted = bytes.clone();
+        corrupted[middle + record_len + 1] ^= 0x10;
+        std::fs::write(&segment, &corrupted).unwrap();
+        let recovery = WalHandler::recover(dir.path(), 0, &[]).unwrap();
+        assert_eq!((recovery.entries.len(), recovery.torn_bytes), (2, record_len as u64));
+    }
+
+    #[test]
+    fn test_wal_segment_rotation_and_compaction() {
+        let dir = TempDir::new("wal_segments");
+        let archive = dir.join("archive");
+        let policy = SegmentPolicy { max_bytes: u64::MAX, max_entries: 2 };
+
+        let mut wal = WalHandler::open(dir.path(), &WalRecovery::default(), policy, Durability::FlushOnly).unwrap();
+        for timestamp in 1..=5 {
+            wal.write_entry(&LogEntry::Expire { timestamp }).unwrap();
+        }
+
+        // Segment berisi seq 1-2, 3-4, 5 (aktif)
+        let manifest = std::fs::read_to_string(dir.join(MANIFEST)).unwrap();
+        assert!(manifest.contains("sealed wal-00000000000000000001.log"));
+        assert!(manifest.contains("sealed wal-00000000000000000003.log"));
+        assert!(manifest.contains("active wal-00000000000000000005.log"));
+
+        let recovery = WalHandler::recover(dir.path(), 0, &[]).unwrap();
+        assert_eq!(recovery.entries.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+
+        // Snapshot di seq 3: hanya segment 1-2 yang seluruhnya tercakup
+        assert_eq!(wal.compact(3, Some(&archive)).unwrap(), 1);
+        assert!(archive.join("wal-00000000000000000001.log").exists());
+        let manifest = std::fs::read_to_string(dir.join(MANIFEST)).unwrap();
+        assert!(!manifest.contains("wal-00000000000000000001.log"));
+
+        // Recovery setelah snapshot seq 4 melewati segment yang sudah tercakup, lalu menulis lanjut dari seq 6
+        let recovery = WalHandler::recover(dir.path(), 4, &[]).unwrap();
+        assert_eq!(recovery.entries.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(), vec![5]);
+        drop(wal);
+        let mut wal = WalHandler::open(dir.path(), &recovery, policy, Durability::FlushOnly).unwrap();
+        wal.write_entry(&LogEntry::Expire { timestamp: 6 }).unwrap();
+        assert_eq!(wal.last_seq(), 6);
+    }
+
+    #[test]
+    fn test_wal_from_other_matching_version_requires_migration() {
+        let dir = TempDir::new("wal_version");
+
+        let mut wal = WalHandler::open(dir.path(), &WalRecovery::default(), SegmentPolicy::default(), Durability::default()).unwrap();
+        for timestamp in 1..=2 {
+            wal.write_entry(&LogEntry::Expire { timestamp }).unwrap();
+        }
+        let segment = wal.active_segment().to_path_buf();
+        drop(wal);
+
+        // Ubah matching version di header seolah ditulis engine versi lama
+        let mut bytes = std::fs::read(&segment).unwrap();
+        assert_eq!(&bytes[..8], MAGIC);
+        bytes[12..16].copy_from_slice(&(MATCHING_VERSION - 1).to_le_bytes());
+        std::fs::write(&segment, &bytes).unwrap();
+
+        match WalHandler::recover(dir.path(), 0, &[]) {
+            Err(WalError::IncompatibleEngine { found, expected, .. }) => {
+                assert_eq!((found, expected), (MATCHING_VERSION - 1, MATCHING_VERSION));
+            }
+            other => panic!("Harusnya ditolak, dapat {:?}", other.map(|r| r.entries.len())),
+        }
+
+        // Dengan migrasi eksplisit, entry diubah sebelum di-replay
+        fn to_millis(entry: LogEntry) -> LogEntry {
+            match entry {
+                LogEntry::Expire { timestamp } => LogEntry::Expire { timestamp: timestamp * 1000 },
+                other => other,
+            }
+        }
+        let migrations = [Migration { from_version: MATCHING_VERSION - 1, migrate: to_millis }];
+        let recovery = WalHandler::recover(dir.path(), 0, &migrations).unwrap();
+        assert_eq!(recovery.entries[1], (2, LogEntry::Expire { timestamp: 2000 }));
+
+        // Entry baru tidak dicampur ke segment versi lama
+        let mut wal = WalHandler::open(dir.path(), &recovery, SegmentPolicy::default(), Durability::default()).unwrap();
+        wal.write_entry(&LogEntry::Expire { timestamp: 3000 }).unwrap();
+        assert_ne!(wal.active_segment(), segment.as_path());
+        drop(wal);
+        let recovery = WalHandler::recover(dir.path(), 0, &migrations).unwrap();
+        assert_eq!(recovery.entries.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        // Format header yang tidak dikenal tidak bisa dimigrasi
+        bytes[8..12].copy_from_slice(&99u32.to_le_bytes());
+        std::fs::write(&segment, &bytes).unwrap();
+        assert!(matches!(WalHandler::recover(dir.path(), 0, &migrations), Err(WalError::UnsupportedFormat { found: 99, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_group_commit_acks_only_after_batch_is_durable() {
+        let dir = TempDir::new("group_commit");
+        let config = market_config(&dir)
+            .with_durability(Durability::GroupCommit { max_entries: 2, max_delay: Duration::from_secs(3600) });
+
+        let (tx, rx) = mpsc::channel(8);
+        let (broadcast_tx, mut events) = broadcast::channel(8);
+        let processor = MarketProcessor::new(config, rx, broadcast_tx).unwrap();
+        tokio::spawn(processor.run());
+
+        // Entry pertama belum durable: balasan ditahan sampai batch penuh
+        let (command, mut first) = deposit_command(1, 1, Asset::Quote, 10);
+        tx.send(command).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(first.try_recv().is_err());
+
+        let (command, second) = deposit_command(2, 2, Asset::Quote, 10);
+        tx.send(command).await.unwrap();
+        assert!(second.await.unwrap().is_ok());
+        assert!(first.await.unwrap().is_ok());
+
+        // Reject di tengah batch yang terbuka ikut ditahan, tidak mendahului event entry sebelumnya
+        let (command, mut third) = deposit_command(3, 3, Asset::Quote, 10);
+        tx.send(command).await.unwrap();
+        let (responder, mut rejected) = oneshot::channel();
+        tx.send(Command::CancelOrder { user_id: 3, order_id: 99, client_order_id: None, responder }).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(rejected.try_recv().is_err());
+        while let Ok(market_event) = events.try_recv() {
+            assert!(!matches!(market_event.event, EngineEvent::FundsDeposited { user_id: 3, .. }));
+            assert!(!matches!(market_event.event, EngineEvent::OrderRejected { .. }));
+        }
+
+        // Query dijawab tanpa menutup batch (tidak ada fsync per query)
+        let (responder, depth) = oneshot::channel();
+        tx.send(Command::GetDepth { limit: 10, responder }).await.unwrap();
+        depth.await.unwrap();
+        assert!(third.try_recv().is_err());
+
+        // Batch penuh: balasan dan event dilepas sesuai urutan
+        let (command, fourth) = deposit_command(4, 4, Asset::Quote, 10);
+        tx.send(command).await.unwrap();
+        assert!(fourth.await.unwrap().is_ok());
+        assert!(third.try_recv().unwrap().is_ok());
+        assert!(matches!(rejected.try_recv().unwrap(), Err(EngineError::OrderRejected { order_id: 99, .. })));
+        assert!(matches!(events.try_recv().unwrap().event, EngineEvent::FundsDeposited { user_id: 3, .. }));
+        assert!(matches!(events.try_recv().unwrap().event, EngineEvent::OrderRejected { id: 99, .. }));
+        assert!(matches!(events.try_recv().unwrap().event, EngineEvent::FundsDeposited { user_id: 4, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_replay_divergence_fails_startup() {
+        let dir = TempDir::new("replay_divergence");
+        let config = market_config(&dir);
+
+        let (tx, rx) = mpsc::channel(8);
+        let (broadcast_tx, _) = broadcast::channel(16);
+        let engine = tokio::spawn(MarketProcessor::new(config.clone(), rx, broadcast_tx.clone()).unwrap().run());
+        let (responder, stop) = oneshot::channel();
+        let commands = [
+            deposit_command(1, 1, Asset::Base, 5),
+            (Command::PlaceStopOrder {
+                user_id: 1, order_id: 1, side: Side::Ask, trigger_price: 90, limit_price: None, quantity: 5,
+                stp: Default::default(), client_order_id: None, responder,
+            }, stop),
+        ];
+        for (command, reply) in commands {
+            tx.send(command).await.unwrap();
+            assert!(reply.await.unwrap().is_ok());
+        }
+        drop(tx);
+        engine.await.unwrap();
+
+        // Log mencatat stop ter-trigger, tapi replay tidak pernah men-trigger-nya
+        let recovery = WalHandler::recover(&config.wal_dir, 0, &[]).unwrap();
+        let mut wal = WalHandler::open(&config.wal_dir, &recovery, SegmentPolicy::default(), Durability::default()).unwrap();
+        wal.write_entry(&LogEntry::StopTriggered { order_id: 1 }).unwrap();
+        drop(wal);
+
+        let (_tx, rx) = mpsc::channel(8);
+        match MarketProcessor::new(config, rx, broadcast_tx) {
+            Err(WalError::ReplayDivergence { input_seq, .. }) => assert_eq!(input_seq, 3),
+            other => panic!("Harusnya ditolak, dapat {:?}", other.err()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wal_write_failure_halts_market_without_state_change() {
+        // Disk yang bisa dibuat gagal: write gagal setelah menulis setengah frame (torn write)
+        struct FailingWriter {
+            file: File,
+            fail: Arc<AtomicBool>,
+        }
+        impl Write for FailingWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.fail.load(Ordering::SeqCst) {
+                    self.file.write_all(&buf[..buf.len() / 2])?;
+                    return Err(io::Error::other("disk full"));
+                }
+                self.file.write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                self.file.flush()
+            }
+        }
+        impl SegmentWriter for FailingWriter {
+            fn sync(&mut self) -> io::Result<()> {
+                self.file.sync_data()
+            }
+            fn truncate(&mut self, len: u64) -> io::Result<()> {
+                self.file.set_len(len)
+            }
+        }
+
+        let dir = TempDir::new("fail_closed");
+        let config = market_config(&dir);
+        let wal_dir = config.wal_dir.clone();
+
+        let (tx, rx) = mpsc::channel(8);
+        let (broadcast_tx, _) = broadcast::channel(16);
+        let processor = MarketProcessor::new(config, rx, broadcast_tx).unwrap();
+        let segment = OpenOptions::new().append(true).open(segment_path(Path::new(&wal_dir), 1)).unwrap();
+        let fail = Arc::new(AtomicBool::new(false));
+        let processor = processor.with_wal_writer(Box::new(FailingWriter { file: segment, fail: fail.clone() }));
+        tokio::spawn(processor.run());
+
+        let (command, reply) = deposit_command(1, 1, Asset::Quote, 1_000);
+        tx.send(command).await.unwrap();
+        assert!(reply.await.unwrap().is_ok());
+
+        // WAL gagal: order ditolak dan tidak pernah menyentuh buku
+        fail.store(true, Ordering::SeqCst);
+        let (command, reply) = place_command(7, 1, Side::Bid, 10, 5);
+        tx.send(command).await.unwrap();
+        assert_eq!(reply.await.unwrap().unwrap_err(), EngineError::MarketHalted);
+
+        let (responder, depth) = oneshot::channel();
+        tx.send(Command::GetDepth { limit: 10, responder }).await.unwrap();
+        assert!(depth.await.unwrap().1.is_empty());
+
+        // Disk sudah pulih, tapi market tetap read-only sampai operator resume
+        fail.store(false, Ordering::SeqCst);
+        let (command, reply) = deposit_command(2, 2, Asset::Quote, 1_000);
+        tx.send(command).await.unwrap();
+        assert_eq!(reply.await.unwrap().unwrap_err(), EngineError::MarketHalted);
+
+        let (responder, resumed) = oneshot::channel();
+        tx.send(Command::Resume { responder }).await.unwrap();
+        assert!(resumed.await.unwrap().is_ok());
+
+        // Order ID yang sama bukan duplikat: percobaan pertama benar-benar tidak tercatat
+        let (command, reply) = place_command(7, 1, Side::Bid, 10, 5);
+        tx.send(command).await.unwrap();
+        assert!(reply.await.unwrap().is_ok());
+
+        // Sisa frame yang gagal sudah dipotong, log hanya berisi entry yang dieksekusi
+        let recovery = WalHandler::recover(&wal_dir, 0, &[]).unwrap();
+        assert_eq!(recovery.entries.len(), 2);
+        assert!(matches!(recovery.entries[1].1, LogEntry::Place { order_id: 7, .. }));
+    }
+}
@@ -1,9 +1,11 @@
 // crates/trading-cli/src/main.rs
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use trading::trading_engine_client::TradingEngineClient;
-use trading::{PlaceOrderRequest, DepthRequest, Side};
+use trading::{PlaceOrderRequest, MarketOrderRequest, DepthRequest, FundsRequest, Side, TimeInForce, PostOnlyMode, StpMode, Asset};
 
+// Kode hasil generate dari proto, komentar proto ikut menjadi doc comment
+#[allow(clippy::doc_lazy_continuation)]
 pub mod trading {
     tonic::include_proto!("trading");
 }
@@ -12,10 +14,99 @@ pub mod trading {
 #[command(name = "Velocity CLI")]
 #[command(about = "High-Performance DEX CLI Client", long_about = None)]
 struct Cli {
+    // Market tujuan untuk semua command
+    #[arg(short, long, global = true, default_value = "SOL_USDC")]
+    symbol: String,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum Tif {
+    Gtc,
+    Ioc,
+    Fok,
+    Gtd,
+}
+
+impl From<Tif> for TimeInForce {
+    fn from(tif: Tif) -> Self {
+        match tif {
+            Tif::Gtc => TimeInForce::Gtc,
+            Tif::Ioc => TimeInForce::Ioc,
+            Tif::Fok => TimeInForce::Fok,
+            Tif::Gtd => TimeInForce::Gtd,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PostOnly {
+    Off,
+    Reject,
+    Slide,
+}
+
+impl From<PostOnly> for PostOnlyMode {
+    fn from(mode: PostOnly) -> Self {
+        match mode {
+            PostOnly::Off => PostOnlyMode::PostOnlyDisabled,
+            PostOnly::Reject => PostOnlyMode::PostOnlyReject,
+            PostOnly::Slide => PostOnlyMode::PostOnlySlide,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Stp {
+    CancelMaker,
+    CancelTaker,
+    CancelBoth,
+    DecrementAndCancel,
+}
+
+impl From<Stp> for StpMode {
+    fn from(mode: Stp) -> Self {
+        match mode {
+            Stp::CancelMaker => StpMode::CancelMaker,
+            Stp::CancelTaker => StpMode::CancelTaker,
+            Stp::CancelBoth => StpMode::CancelBoth,
+            Stp::DecrementAndCancel => StpMode::DecrementAndCancel,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AssetArg {
+    Base,
+    Quote,
+}
+
+impl From<AssetArg> for Asset {
+    fn from(asset: AssetArg) -> Self {
+        match asset {
+            AssetArg::Base => Asset::Base,
+            AssetArg::Quote => Asset::Quote,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SideArg {
+    Buy,
+    Sell,
+}
+
+impl From<SideArg> for Side {
+    fn from(side: SideArg) -> Self {
+        match side {
+            SideArg::Buy => Side::Bid,
+            SideArg::Sell => Side::Ask,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     Buy {
@@ -27,6 +118,20 @@ enum Commands {
         user_id: u64,
         #[arg(long, default_value_t = 0)] // Jika 0, generate random
         order_id: u64,
+        #[arg(long, value_enum, default_value_t = Tif::Gtc)]
+        tif: Tif,
+        #[arg(long, default_value_t = 0)] // GTD: Unix milliseconds
+        expire_at: u64,
+        #[arg(long, value_enum, default_value_t = PostOnly::Off)]
+        post_only: PostOnly,
+        #[arg(long, default_value_t = 0)] // Iceberg: 0 = tampilkan seluruh quantity
+        display_qty: u64,
+        #[arg(long, value_enum, default_value_t = Stp::CancelMaker)]
+        stp: Stp,
+        #[arg(long, default_value_t = 0)] // 0 = tanpa STP group
+        stp_group: u64,
+        #[arg(long, default_value = "")] // Opsional, unik per user di antara order yang masih hidup
+        client_id: String,
     },
     Sell {
         #[arg(short, long)]
@@ -37,10 +142,109 @@ enum Commands {
         user_id: u64,
         #[arg(long, default_value_t = 0)]
         order_id: u64,
+        #[arg(long, value_enum, default_value_t = Tif::Gtc)]
+        tif: Tif,
+        #[arg(long, default_value_t = 0)] // GTD: Unix milliseconds
+        expire_at: u64,
+        #[arg(long, value_enum, default_value_t = PostOnly::Off)]
+        post_only: PostOnly,
+        #[arg(long, default_value_t = 0)] // Iceberg: 0 = tampilkan seluruh quantity
+        display_qty: u64,
+        #[arg(long, value_enum, default_value_t = Stp::CancelMaker)]
+        stp: Stp,
+        #[arg(long, default_value_t = 0)] // 0 = tanpa STP group
+        stp_group: u64,
+        #[arg(long, default_value = "")] // Opsional, unik per user di antara order yang masih hidup
+        client_id: String,
+    },
+    MarketBuy {
+        #[arg(short, long)]
+        quantity: u64,
+        #[arg(short, long, default_value_t = 1)]
+        user_id: u64,
+        #[arg(long, default_value_t = 0)]
+        order_id: u64,
+        #[arg(long, value_enum, default_value_t = Stp::CancelMaker)]
+        stp: Stp,
+        #[arg(long, default_value_t = 0)] // 0 = tanpa STP group
+        stp_group: u64,
+        #[arg(long, default_value = "")] // Opsional, unik per user di antara order yang masih hidup
+        client_id: String,
+    },
+    MarketSell {
+        #[arg(short, long)]
+        quantity: u64,
+        #[arg(short, long, default_value_t = 1)]
+        user_id: u64,
+        #[arg(long, default_value_t = 0)]
+        order_id: u64,
+        #[arg(long, value_enum, default_value_t = Stp::CancelMaker)]
+        stp: Stp,
+        #[arg(long, default_value_t = 0)] // 0 = tanpa STP group
+        stp_group: u64,
+        #[arg(long, default_value = "")] // Opsional, unik per user di antara order yang masih hidup
+        client_id: String,
     },
     Cancel {
+        #[arg(short, long, default_value_t = 0)]
+        order_id: u64,
+        #[arg(long, default_value = "")] // Cancel lewat client order ID (order_id diabaikan)
+        client_id: String,
+        #[arg(short, long, default_value_t = 1)]
+        user_id: u64,
+    },
+    // Kill switch: cancel semua order user (opsional per side / rentang harga)
+    CancelAll {
+        #[arg(short, long, default_value_t = 1)]
+        user_id: u64,
+        #[arg(long, value_enum)] // Kosong = kedua sisi
+        side: Option<SideArg>,
+        #[arg(long, default_value_t = 0)] // 0 = tanpa batas bawah
+        min_price: u64,
+        #[arg(long, default_value_t = 0)] // 0 = tanpa batas atas
+        max_price: u64,
+    },
+    Amend {
         #[arg(short, long)]
         order_id: u64,
+        #[arg(short, long)]
+        price: u64,
+        #[arg(short, long)]
+        quantity: u64,
+        #[arg(short, long, default_value_t = 1)]
+        user_id: u64,
+    },
+    Deposit {
+        #[arg(short, long, value_enum)]
+        asset: AssetArg,
+        #[arg(long)]
+        amount: u64,
+        #[arg(short, long)] // Reference id mutasi, retry dengan id yang sama tidak diterapkan dua kali
+        journal_id: u64,
+        #[arg(short, long, default_value_t = 1)]
+        user_id: u64,
+    },
+    Withdraw {
+        #[arg(short, long, value_enum)]
+        asset: AssetArg,
+        #[arg(long)]
+        amount: u64,
+        #[arg(short, long)] // Reference id mutasi, retry dengan id yang sama tidak diterapkan dua kali
+        journal_id: u64,
+        #[arg(short, long, default_value_t = 1)]
+        user_id: u64,
+    },
+    // Status satu order (by order id atau client order id)
+    Status {
+        #[arg(short, long, default_value_t = 0)]
+        order_id: u64,
+        #[arg(long, default_value = "")]
+        client_id: String,
+        #[arg(short, long, default_value_t = 1)]
+        user_id: u64,
+    },
+    // Semua order hidup milik user
+    Orders {
         #[arg(short, long, default_value_t = 1)]
         user_id: u64,
     },
@@ -48,6 +252,12 @@ enum Commands {
         #[arg(short, long, default_value_t = 10)]
         limit: u32,
     },
+    // Reference data instrument (tick/lot size, batas order, desimal)
+    Instrument,
+    // Paksa snapshot state market (operator)
+    Snapshot,
+    // Buka kembali market yang berhenti karena WAL gagal ditulis (operator)
+    Resume,
 }
 
 #[tokio::main]
@@ -58,7 +268,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut client = TradingEngineClient::connect("http://[::1]:50051").await?;
 
     match cli.command {
-        Commands::Buy { price, quantity, user_id, order_id } => {
+        Commands::Buy { price, quantity, user_id, order_id, tif, expire_at, post_only, display_qty, stp, stp_group, client_id } => {
             let final_oid = if order_id == 0 { rand::random() } else { order_id };
             
             println!("Sending BUY Order... ID: {}", final_oid);
@@ -69,12 +279,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 side: Side::Bid as i32,
                 price,
                 quantity,
+                time_in_force: TimeInForce::from(tif) as i32,
+                post_only: PostOnlyMode::from(post_only) as i32,
+                stp_mode: StpMode::from(stp) as i32,
+                stp_group,
+                display_quantity: display_qty,
+                expire_at,
+                symbol: cli.symbol.clone(),
+                client_order_id: client_id,
             };
             
             let response = client.place_limit_order(request).await?;
             println!("RESPONSE: {:#?}", response.into_inner());
         }
-        Commands::Sell { price, quantity, user_id, order_id } => {
+        Commands::Sell { price, quantity, user_id, order_id, tif, expire_at, post_only, display_qty, stp, stp_group, client_id } => {
             let final_oid = if order_id == 0 { rand::random() } else { order_id };
 
             println!("Sending SELL Order... ID: {}", final_oid);
@@ -85,29 +303,149 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 side: Side::Ask as i32,
                 price,
                 quantity,
+                time_in_force: TimeInForce::from(tif) as i32,
+                post_only: PostOnlyMode::from(post_only) as i32,
+                stp_mode: StpMode::from(stp) as i32,
+                stp_group,
+                display_quantity: display_qty,
+                expire_at,
+                symbol: cli.symbol.clone(),
+                client_order_id: client_id,
             };
 
             let response = client.place_limit_order(request).await?;
             println!("RESPONSE: {:#?}", response.into_inner());
         }
-        Commands::Cancel { order_id, user_id } => {
+        Commands::MarketBuy { quantity, user_id, order_id, stp, stp_group, client_id } => {
+            let final_oid = if order_id == 0 { rand::random() } else { order_id };
+
+            println!("Sending MARKET BUY Order... ID: {}", final_oid);
+
+            let request = MarketOrderRequest {
+                user_id,
+                order_id: final_oid,
+                side: Side::Bid as i32,
+                quantity,
+                stp_mode: StpMode::from(stp) as i32,
+                stp_group,
+                symbol: cli.symbol.clone(),
+                client_order_id: client_id,
+            };
+
+            let response = client.place_market_order(request).await?;
+            println!("RESPONSE: {:#?}", response.into_inner());
+        }
+        Commands::MarketSell { quantity, user_id, order_id, stp, stp_group, client_id } => {
+            let final_oid = if order_id == 0 { rand::random() } else { order_id };
+
+            println!("Sending MARKET SELL Order... ID: {}", final_oid);
+
+            let request = MarketOrderRequest {
+                user_id,
+                order_id: final_oid,
+                side: Side::Ask as i32,
+                quantity,
+                stp_mode: StpMode::from(stp) as i32,
+                stp_group,
+                symbol: cli.symbol.clone(),
+                client_order_id: client_id,
+            };
+
+            let response = client.place_market_order(request).await?;
+            println!("RESPONSE: {:#?}", response.into_inner());
+        }
+        Commands::Cancel { order_id, client_id, user_id } => {
             let request = trading::CancelOrderRequest {
                 user_id,
                 order_id,
+                symbol: cli.symbol.clone(),
+                client_order_id: client_id,
             };
             let response = client.cancel_order(request).await?;
             println!("CANCEL RESPONSE: {:#?}", response.into_inner());
         }
+        Commands::CancelAll { user_id, side, min_price, max_price } => {
+            let request = trading::MassCancelRequest {
+                user_id,
+                side: side.map_or(Side::Unspecified, Side::from) as i32,
+                min_price,
+                max_price,
+                symbol: cli.symbol.clone(),
+            };
+            let response = client.mass_cancel(request).await?;
+            println!("MASS CANCEL RESPONSE: {:#?}", response.into_inner());
+        }
+        Commands::Status { order_id, client_id, user_id } => {
+            let request = trading::GetOrderRequest {
+                user_id,
+                order_id,
+                client_order_id: client_id,
+                symbol: cli.symbol.clone(),
+            };
+            let response = client.get_order(request).await?;
+            println!("ORDER STATUS: {:#?}", response.into_inner());
+        }
+        Commands::Orders { user_id } => {
+            let request = trading::ListOpenOrdersRequest {
+                user_id,
+                symbol: cli.symbol.clone(),
+            };
+            let orders = client.list_open_orders(request).await?.into_inner().orders;
+
+            println!("\n--- OPEN ORDERS {} (user {}) ---", cli.symbol, user_id);
+            println!("{:<20} | {:<4} | {:<10} | {:<10} | {:<10} | {:<15}", "ORDER ID", "SIDE", "PRICE", "FILLED", "REMAINING", "STATE");
+            for order in orders {
+                let side = if order.side == Side::Bid as i32 { "BUY" } else { "SELL" };
+                let state = trading::OrderState::try_from(order.state).unwrap_or(trading::OrderState::Unspecified);
+                println!(
+                    "{:<20} | {:<4} | {:<10} | {:<10} | {:<10} | {:<15}",
+                    order.order_id, side, order.price, order.filled_quantity, order.remaining_quantity, state.as_str_name()
+                );
+            }
+        }
+        Commands::Amend { order_id, price, quantity, user_id } => {
+            let request = trading::AmendOrderRequest {
+                user_id,
+                order_id,
+                price,
+                quantity,
+                symbol: cli.symbol.clone(),
+            };
+            let response = client.amend_order(request).await?;
+            println!("AMEND RESPONSE: {:#?}", response.into_inner());
+        }
+        Commands::Deposit { asset, amount, journal_id, user_id } => {
+            let request = FundsRequest {
+                user_id,
+                asset: Asset::from(asset) as i32,
+                amount,
+                symbol: cli.symbol.clone(),
+                journal_id,
+            };
+            let response = client.deposit(request).await?;
+            println!("DEPOSIT RESPONSE: {:#?}", response.into_inner());
+        }
+        Commands::Withdraw { asset, amount, journal_id, user_id } => {
+            let request = FundsRequest {
+                user_id,
+                asset: Asset::from(asset) as i32,
+                amount,
+                symbol: cli.symbol.clone(),
+                journal_id,
+            };
+            let response = client.withdraw(request).await?;
+            println!("WITHDRAW RESPONSE: {:#?}", response.into_inner());
+        }
         Commands::Depth { limit } => {
             let request = DepthRequest {
-                symbol: "SOL_USDC".to_string(),
+                symbol: cli.symbol.clone(),
                 limit,
             };
             
             let response = client.get_order_book_depth(request).await?;
             let inner = response.into_inner();
             
-            println!("\n=== ORDER BOOK (Top {}) ===", limit);
+            println!("\n=== ORDER BOOK {} (Top {}) ===", cli.symbol, limit);
             println!("ASKS (Jual):");
             // Balik urutan asks agar harga termahal di atas 
             for level in inner.asks.iter().rev() {
@@ -122,6 +460,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             println!("=============================\n");
         }
+        Commands::Instrument => {
+            let request = trading::InstrumentRequest { symbol: cli.symbol.clone() };
+            let response = client.get_instrument(request).await?;
+            println!("INSTRUMENT: {:#?}", response.into_inner());
+        }
+        Commands::Snapshot => {
+            let request = trading::SnapshotRequest { symbol: cli.symbol.clone() };
+            let response = client.take_snapshot(request).await?.into_inner();
+            println!("SNAPSHOT {} written at seq {}", cli.symbol, response.seq);
+        }
+        Commands::Resume => {
+            client.resume_market(trading::ResumeRequest { symbol: cli.symbol.clone() }).await?;
+            println!("MARKET {} resumed", cli.symbol);
+        }
     }
 
     Ok(())